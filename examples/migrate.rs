@@ -0,0 +1,43 @@
+use std::env;
+use std::process;
+
+use pikmin::writer::db_mysql::migration::Migrator;
+use pikmin::writer::Trade;
+use pikmin::MySQLWriter;
+
+// Migrates a trade table to the current definition.
+//
+// cargo run --example migrate -- <table> <database_url> [--dry-run]
+fn main() {
+    let args: Vec<String> = env::args().collect();
+    if args.len() < 3 {
+        eprintln!("usage: {} <table> <database_url> [--dry-run]", args[0]);
+        process::exit(1);
+    }
+    let table = &args[1];
+    let dry_run = args.iter().any(|a| a == "--dry-run");
+
+    let result = MySQLWriter::new(table, &args[2]).and_then(|writer| {
+        let migrator = Migrator::new(writer.pool());
+        if dry_run {
+            migrator.plan::<Trade>(table)
+        } else {
+            migrator.migrate::<Trade>(table)
+        }
+    });
+
+    match result {
+        Ok(stmts) => {
+            if stmts.is_empty() {
+                println!("{} is up to date", table);
+            }
+            for stmt in stmts {
+                println!("{}", stmt);
+            }
+        }
+        Err(e) => {
+            eprintln!("error: {}", e);
+            process::exit(1);
+        }
+    }
+}
@@ -1,6 +1,5 @@
 use std::path::PathBuf;
 use std::thread;
-use std::time::Duration;
 
 use chrono::offset::TimeZone;
 use chrono::Utc;
@@ -13,38 +12,28 @@ use pikmin::StdOutWriter;
 fn main() {
     // by using thread, you can run multiple downloaders
     let liquid = thread::spawn(move || {
-        while {
-            // download data from 2019-01-01T01:01:01Z to 2019-01-01T01:03:01Z
-            // output the downloaded data to standard out (println!)
-            let downloader = LiquidDownloader::new(
-                Utc.ymd(2019, 1, 1).and_hms(1, 1, 1),
-                Utc.ymd(2019, 1, 1).and_hms(1, 3, 1),
-            );
-
-            // Locate progress file to `/tmp/qn-progress.txt`.
-            // You can control the starting point of downloading
-            // by preparing this file before you run.
-            let mut recorder = FileRecorder::new(PathBuf::from("/tmp/qn-progress.txt"));
-
-            // write out to standard out. simple writer for debugging
-            let mut writer = StdOutWriter::default();
-
-            println!("start QnDownloader");
-
-            // run!
-            match downloader.run(&mut writer, &mut recorder) {
-                Ok(_) => {
-                    println!("finished");
-                    false
-                }
-                Err(e) => {
-                    println!("error: {}", e);
-                    println!("retry...");
-                    thread::sleep(Duration::from_secs(5));
-                    true
-                }
-            }
-        } {}
+        // download data from 2019-01-01T01:01:01Z to 2019-01-01T01:03:01Z
+        // output the downloaded data to standard out (println!)
+        let downloader = LiquidDownloader::new(
+            Utc.ymd(2019, 1, 1).and_hms(1, 1, 1),
+            Utc.ymd(2019, 1, 1).and_hms(1, 3, 1),
+        );
+
+        // Locate progress file to `/tmp/qn-progress.txt`.
+        // You can control the starting point of downloading
+        // by preparing this file before you run.
+        let mut recorder = FileRecorder::new(PathBuf::from("/tmp/qn-progress.txt"));
+
+        // write out to standard out. simple writer for debugging
+        let mut writer = StdOutWriter::default();
+
+        println!("start QnDownloader");
+
+        // run! see `examples/main.rs` to retry after errors
+        match downloader.run(&mut writer, &mut recorder) {
+            Ok(report) => println!("finished: {:?}", report.stop_reason),
+            Err(e) => println!("error: {}", e),
+        }
     });
 
     match liquid
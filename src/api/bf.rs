@@ -1,10 +1,13 @@
+use std::sync::Arc;
+
 use log::trace;
-use reqwest::Client;
 use serde_derive::{Deserialize, Serialize};
 
-use crate::api::ExchangeAPIClient;
+use crate::api::{ErrorBody, ExchangeAPIClient};
+use crate::downloader::report::Traffic;
 use crate::error::Error;
 use crate::error::Result;
+use crate::transport::{ReqwestTransport, Transport};
 
 #[derive(Serialize, Deserialize, Debug)]
 pub struct BfGetExecution {
@@ -17,9 +20,17 @@ pub struct BfGetExecution {
     pub sell_child_order_acceptance_id: String,
 }
 
+/// An error body of bitFlyer, e.g. `{"status":-1,"error_message":"..."}`.
+#[derive(Deserialize, Debug)]
+struct BfErrorBody {
+    status: i64,
+    error_message: String,
+}
+
 #[derive(Debug)]
 pub struct BfAPI {
-    reqwest_client: Client,
+    transport: Arc<dyn Transport>,
+    traffic: Traffic,
     product_code: &'static str,
 }
 
@@ -32,11 +43,21 @@ impl Default for BfAPI {
 impl BfAPI {
     pub fn new() -> Self {
         Self {
-            reqwest_client: Client::new(),
+            transport: Arc::new(ReqwestTransport::new()),
+            traffic: Traffic::default(),
             product_code: "FX_BTC_JPY",
         }
     }
 
+    pub fn with_transport(mut self, transport: Arc<dyn Transport>) -> Self {
+        self.transport = transport;
+        self
+    }
+
+    pub fn product_code(&self) -> &str {
+        self.product_code
+    }
+
     pub fn executions(&self, before_id: u64, limit: usize) -> Result<Vec<BfGetExecution>> {
         trace!("executions -- before_id:{}, limit:{}", before_id, limit);
         let path = format!(
@@ -53,7 +74,24 @@ impl ExchangeAPIClient for BfAPI {
         "https://api.bitflyer.com"
     }
 
-    fn reqwest_client(&self) -> &Client {
-        &self.reqwest_client
+    fn transport(&self) -> &dyn Transport {
+        self.transport.as_ref()
+    }
+
+    fn exchange_name(&self) -> &'static str {
+        "bitflyer"
+    }
+
+    fn traffic(&self) -> &Traffic {
+        &self.traffic
+    }
+
+    fn decode_error(&self, body: &str) -> Option<ErrorBody> {
+        serde_json::from_str::<BfErrorBody>(body)
+            .ok()
+            .map(|e| ErrorBody {
+                code: Some(e.status.to_string()),
+                message: e.error_message,
+            })
     }
 }
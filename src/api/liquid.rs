@@ -1,11 +1,13 @@
+use std::sync::Arc;
+
 use log::trace;
-use reqwest::Client;
-use reqwest::RequestBuilder;
 use serde_derive::{Deserialize, Serialize};
 
-use crate::api::ExchangeAPIClient;
+use crate::api::{ErrorBody, ExchangeAPIClient};
+use crate::downloader::report::Traffic;
 use crate::error::Error;
 use crate::error::Result;
+use crate::transport::{HttpRequest, ReqwestTransport, Transport};
 
 #[derive(Serialize, Deserialize, Debug)]
 pub struct LiquidGetExecution {
@@ -16,9 +18,16 @@ pub struct LiquidGetExecution {
     pub created_at: u64,
 }
 
+/// An error body of Liquid, e.g. `{"message":"..."}`.
+#[derive(Deserialize, Debug)]
+struct LiquidErrorBody {
+    message: String,
+}
+
 #[derive(Debug)]
 pub struct LiquidAPI {
-    reqwest_client: Client,
+    transport: Arc<dyn Transport>,
+    traffic: Traffic,
     product_code: &'static str,
 }
 
@@ -31,11 +40,21 @@ impl Default for LiquidAPI {
 impl LiquidAPI {
     pub fn new() -> Self {
         Self {
-            reqwest_client: Client::new(),
+            transport: Arc::new(ReqwestTransport::new()),
+            traffic: Traffic::default(),
             product_code: "5",
         }
     }
 
+    pub fn with_transport(mut self, transport: Arc<dyn Transport>) -> Self {
+        self.transport = transport;
+        self
+    }
+
+    pub fn product_code(&self) -> &str {
+        self.product_code
+    }
+
     pub fn executions(&self, timestamp: u64, limit: usize) -> Result<Vec<LiquidGetExecution>> {
         trace!("executions -- timestamp:{}, limit:{}", timestamp, limit);
         let path = format!(
@@ -52,11 +71,28 @@ impl ExchangeAPIClient for LiquidAPI {
         "https://api.liquid.com"
     }
 
-    fn reqwest_client(&self) -> &Client {
-        &self.reqwest_client
+    fn transport(&self) -> &dyn Transport {
+        self.transport.as_ref()
+    }
+
+    fn exchange_name(&self) -> &'static str {
+        "liquid"
+    }
+
+    fn traffic(&self) -> &Traffic {
+        &self.traffic
+    }
+
+    fn decode_error(&self, body: &str) -> Option<ErrorBody> {
+        serde_json::from_str::<LiquidErrorBody>(body)
+            .ok()
+            .map(|e| ErrorBody {
+                code: None,
+                message: e.message,
+            })
     }
 
-    fn with_common_header(&self, r: RequestBuilder) -> RequestBuilder {
+    fn with_common_header(&self, r: HttpRequest) -> HttpRequest {
         r.header("X-Quoine-API-Version", "2")
             .header("Content-Type", "application/json")
     }
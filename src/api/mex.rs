@@ -1,12 +1,15 @@
+use std::sync::Arc;
+
 use chrono::DateTime;
 use chrono::Utc;
 use log::trace;
-use reqwest::Client;
 use serde_derive::{Deserialize, Serialize};
 
-use crate::api::ExchangeAPIClient;
+use crate::api::{ErrorBody, ExchangeAPIClient};
+use crate::downloader::report::Traffic;
 use crate::error::Error;
 use crate::error::Result;
+use crate::transport::{ReqwestTransport, Transport};
 
 #[derive(Serialize, Deserialize, Debug)]
 #[allow(non_snake_case)]
@@ -23,9 +26,22 @@ pub struct MexGetExecution {
     foreignNotional: u64,
 }
 
+/// An error body of BitMEX, e.g. `{"error":{"message":"...","name":"..."}}`.
+#[derive(Deserialize, Debug)]
+struct MexErrorBody {
+    error: MexErrorDetail,
+}
+
+#[derive(Deserialize, Debug)]
+struct MexErrorDetail {
+    message: String,
+    name: Option<String>,
+}
+
 #[derive(Debug)]
 pub struct MexAPI {
-    reqwest_client: Client,
+    transport: Arc<dyn Transport>,
+    traffic: Traffic,
     product_code: &'static str,
 }
 
@@ -38,11 +54,21 @@ impl Default for MexAPI {
 impl MexAPI {
     pub fn new() -> Self {
         Self {
-            reqwest_client: Client::new(),
+            transport: Arc::new(ReqwestTransport::new()),
+            traffic: Traffic::default(),
             product_code: "XBTUSD",
         }
     }
 
+    pub fn with_transport(mut self, transport: Arc<dyn Transport>) -> Self {
+        self.transport = transport;
+        self
+    }
+
+    pub fn product_code(&self) -> &str {
+        self.product_code
+    }
+
     pub fn executions(
         &self,
         start_time: &DateTime<Utc>,
@@ -69,7 +95,24 @@ impl ExchangeAPIClient for MexAPI {
         "https://www.bitmex.com"
     }
 
-    fn reqwest_client(&self) -> &Client {
-        &self.reqwest_client
+    fn transport(&self) -> &dyn Transport {
+        self.transport.as_ref()
+    }
+
+    fn exchange_name(&self) -> &'static str {
+        "bitmex"
+    }
+
+    fn traffic(&self) -> &Traffic {
+        &self.traffic
+    }
+
+    fn decode_error(&self, body: &str) -> Option<ErrorBody> {
+        serde_json::from_str::<MexErrorBody>(body)
+            .ok()
+            .map(|e| ErrorBody {
+                code: e.error.name,
+                message: e.error.message,
+            })
     }
 }
@@ -1,47 +1,153 @@
-use log::trace;
-use reqwest::Client;
-use reqwest::RequestBuilder;
-use reqwest::Response;
+use std::time::Instant;
 
+use tracing::field::Empty;
+use tracing::{debug, debug_span, trace};
+
+use crate::downloader::report::Traffic;
 use crate::error::Error;
 use crate::error::Result;
+use crate::transport::{HttpRequest, Transport};
 
 pub mod bf;
 pub mod liquid;
 pub mod mex;
 
+/// An error reported by an exchange in a response body.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ErrorBody {
+    /// An error code or name, if any.
+    pub code: Option<String>,
+    /// An error message.
+    pub message: String,
+}
+
+/// A client of an exchange API, which sends requests through a `Transport`.
 pub trait ExchangeAPIClient {
     fn base_url(&self) -> &'static str;
-    fn reqwest_client(&self) -> &Client;
+    /// Returns a transport to send requests, e.g. `ReqwestTransport`.
+    fn transport(&self) -> &dyn Transport;
 
     fn url_builder(&self, path: &str) -> String {
         format!("{}{}", self.base_url(), path)
     }
 
-    fn with_common_header(&self, r: RequestBuilder) -> RequestBuilder {
+    fn with_common_header(&self, r: HttpRequest) -> HttpRequest {
         r.header("Content-Type", "application/json")
     }
 
-    fn make_get_request(&self, path: &str) -> RequestBuilder {
+    fn make_get_request(&self, path: &str) -> HttpRequest {
         trace!("make GET request: {}", path);
-        self.with_common_header(self.reqwest_client().get(&self.url_builder(path)))
+        self.with_common_header(HttpRequest::get(&self.url_builder(path)))
     }
 
-    fn resp_to_json<T>(&self, resp: Response) -> reqwest::Result<T>
-        where
-                for<'de> T: serde::Deserialize<'de>,
-    {
-        let mut r = resp;
-        r.json()
+    /// Returns a name of the exchange, used in errors.
+    fn exchange_name(&self) -> &'static str;
+
+    /// Returns counters of requests sent by `send`.
+    fn traffic(&self) -> &Traffic;
+
+    /// Decodes an error reported by the exchange in a response body, if any.
+    fn decode_error(&self, _body: &str) -> Option<ErrorBody> {
+        None
+    }
+
+    /// Converts an unsuccessful response into an error, with the decoded body if possible.
+    fn error_from_body(&self, status: u16, body: String) -> Error {
+        match self.decode_error(&body) {
+            Some(ErrorBody { code, message }) => Error::Exchange {
+                exchange: self.exchange_name().to_owned(),
+                status,
+                code,
+                message,
+            },
+            None => Error::HttpStatus { status, body },
+        }
     }
 
-    fn send<T>(&self, resp: RequestBuilder) -> Result<T>
+    fn send<T>(&self, req: HttpRequest) -> Result<T>
         where
                 for<'de> T: serde::Deserialize<'de>,
     {
-        resp.send()
-            .and_then(|req| req.error_for_status())
-            .and_then(|resp| self.resp_to_json(resp))
-            .map_err(Error::from)
+        let span = debug_span!(
+            "request",
+            exchange = self.exchange_name(),
+            url = %req.url,
+            status = Empty,
+            bytes = Empty,
+            latency_ms = Empty,
+        );
+        let _enter = span.enter();
+        let started = Instant::now();
+
+        self.traffic().add_request();
+        let resp = self.transport().execute(&req)?;
+        let status = resp.status;
+        self.traffic().add_status(status);
+        let body = resp.body;
+        self.traffic().add_bytes(body.len() as u64);
+
+        span.record("status", status);
+        span.record("bytes", body.len());
+        span.record("latency_ms", started.elapsed().as_millis() as u64);
+        debug!(status, bytes = body.len(), "response");
+        if !(200..300).contains(&status) {
+            return Err(self.error_from_body(status, body));
+        }
+        serde_json::from_str(&body).map_err(|e| match self.decode_error(&body) {
+            // some errors are reported with a successful status
+            Some(_) => self.error_from_body(status, body.clone()),
+            None => Error::from(e),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn decode_error_test() {
+        let bf = bf::BfAPI::new();
+        match bf.error_from_body(400, r#"{"status":-500,"error_message":"Key not found","data":null}"#.to_owned()) {
+            Error::Exchange {
+                ref exchange,
+                status,
+                ref code,
+                ref message,
+            } => {
+                assert_eq!(exchange, "bitflyer");
+                assert_eq!(status, 400);
+                assert_eq!(code.as_ref().map(String::as_str), Some("-500"));
+                assert_eq!(message, "Key not found");
+            }
+            e => panic!("unexpected: {:?}", e),
+        }
+
+        let mex = mex::MexAPI::new();
+        match mex.error_from_body(429, r#"{"error":{"message":"Rate limit exceeded","name":"HTTPError"}}"#.to_owned()) {
+            Error::Exchange { ref code, ref message, .. } => {
+                assert_eq!(code.as_ref().map(String::as_str), Some("HTTPError"));
+                assert_eq!(message, "Rate limit exceeded");
+            }
+            e => panic!("unexpected: {:?}", e),
+        }
+
+        let liquid = liquid::LiquidAPI::new();
+        match liquid.error_from_body(422, r#"{"message":"Product not found"}"#.to_owned()) {
+            Error::Exchange { ref code, ref message, .. } => {
+                assert_eq!(*code, None);
+                assert_eq!(message, "Product not found");
+            }
+            e => panic!("unexpected: {:?}", e),
+        }
+
+        // bodies in unknown formats are kept as they are
+        match liquid.error_from_body(502, "<html>Bad Gateway</html>".to_owned()) {
+            Error::HttpStatus { status, ref body } => {
+                assert_eq!(status, 502);
+                assert_eq!(body, "<html>Bad Gateway</html>");
+            }
+            e => panic!("unexpected: {:?}", e),
+        }
     }
 }
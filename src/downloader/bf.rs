@@ -1,13 +1,19 @@
+use std::sync::Arc;
+use std::time::Duration;
+
 use chrono::prelude::*;
 use chrono::Utc;
 use log::{info, warn};
 
 use crate::api::bf::BfAPI;
 use crate::api::bf::BfGetExecution;
+use crate::api::ExchangeAPIClient;
 use crate::downloader::Downloader;
 use crate::downloader::id::num::OrdID;
+use crate::downloader::report::Traffic;
 use crate::error::Error;
 use crate::error::Result;
+use crate::transport::Transport;
 use crate::writer::Trade;
 use crate::writer::Writer;
 
@@ -16,6 +22,9 @@ use crate::writer::Writer;
 pub struct BfDownloader {
     start: u64,
     end: u64,
+    follow: Option<Duration>,
+    retries: u32,
+    sleep_millis: u64,
     api: BfAPI,
 }
 
@@ -31,15 +40,38 @@ impl BfDownloader {
             start: older,
             end: newer,
             api: BfAPI::new(),
+            follow: None,
+            retries: 0,
+            sleep_millis: (1000 * 60) / 500 + 10,
         }
     }
+
+    /// Sends requests through a given transport, e.g. `CassetteTransport` to replay recorded responses.
+    pub fn with_transport(mut self, transport: Arc<dyn Transport>) -> Self {
+        self.api = self.api.with_transport(transport);
+        self
+    }
 }
 
+impl_run_options!(BfDownloader);
+
 impl Downloader for BfDownloader {
     type IDT = u64;
     type ID = OrdID<Self::IDT>;
     type RAW = BfGetExecution;
 
+    fn name(&self) -> String {
+        "bf".to_owned()
+    }
+
+    fn exchange(&self) -> String {
+        self.api.exchange_name().to_owned()
+    }
+
+    fn symbol(&self) -> String {
+        self.api.product_code().to_owned()
+    }
+
     fn start_id(&self) -> Self::IDT {
         self.start
     }
@@ -75,18 +107,85 @@ impl Downloader for BfDownloader {
 
     fn output(&self, u: Vec<Trade>, writer: &mut impl Writer) -> Result<Self::IDT> {
         if let Some(last) = u.last() {
-            writer.write(u.as_slice()).and_then(|num| {
+            let last_id = last.id.parse::<u64>()?;
+            // the last page may overrun the end of the range
+            let mut in_range = Vec::with_capacity(u.len());
+            for trade in u {
+                if trade.id.parse::<u64>()? >= self.end {
+                    in_range.push(trade);
+                }
+            }
+            writer.write(in_range.as_slice()).map(|num| {
                 info!("wrote {} data", num);
-                // TODO: redundant
-                last.id.parse::<u64>().map_err(Error::from)
+                last_id
             })
         } else {
             warn!("no output");
-            Err(Error::NotFound)
+            Err(Error::EmptyPage)
         }
     }
 
     fn sleep_millis(&self) -> u64 {
-        (1000 * 60) / 500 + 10
+        self.sleep_millis
+    }
+
+    fn follow_interval(&self) -> Option<Duration> {
+        self.follow
+    }
+
+    fn max_retries(&self) -> u32 {
+        self.retries
+    }
+
+    fn traffic(&self) -> Option<&Traffic> {
+        Some(self.api.traffic())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use proptest::prelude::*;
+
+    use crate::downloader::report::StopReason;
+    use crate::recorder::memory::MemoryRecorder;
+    use crate::testing::exchange::trades_in_range;
+    use crate::testing::{assert_conforms, FakeExchange, MockWriter};
+    use crate::transport::cassette::fixture;
+
+    use super::*;
+
+    #[test]
+    fn bf_pagination_test() {
+        let cassette = fixture("bitflyer");
+        let downloader = BfDownloader::new(1000, 1005)
+            .with_transport(cassette.clone());
+        let mut writer = MockWriter::new();
+        let report = downloader.run(&mut writer, &mut MemoryRecorder::default()).unwrap();
+
+        // pages are fetched before the oldest ID of the previous page, and the overrun is not written
+        assert_eq!(
+            writer.store.iter().map(|t| t.id.as_str()).collect::<Vec<_>>(),
+            vec!["1004", "1003", "1002", "1001", "1000"],
+        );
+        assert_eq!(report.stop_reason, StopReason::RangeExhausted);
+        assert_eq!((report.batches, report.requests, report.statuses[&200]), (2, 2, 2));
+        assert_eq!(cassette.remaining(), 0);
+    }
+
+    proptest! {
+        #![proptest_config(ProptestConfig::with_cases(32))]
+
+        #[test]
+        fn bf_conformance_test((trades, lo, hi) in trades_in_range()) {
+            let expected: Vec<String> = trades
+                .iter()
+                .filter(|t| lo.id <= t.id && t.id < hi.id)
+                .map(|t| t.id.to_string())
+                .collect();
+            let downloader = BfDownloader::new(lo.id, hi.id)
+                .with_sleep_millis(0)
+                .with_transport(Arc::new(FakeExchange::new(trades)));
+            assert_conforms(&downloader, &expected);
+        }
     }
 }
@@ -1,15 +1,21 @@
+use std::sync::Arc;
+use std::time::Duration;
+
 use chrono::DateTime;
 use chrono::Utc;
 use log::{debug, info, warn};
 
 use crate::api::mex::MexAPI;
 use crate::api::mex::MexGetExecution;
+use crate::api::ExchangeAPIClient;
 use crate::downloader::Downloader;
 use crate::downloader::id::datetime::DateTimeID;
 use crate::downloader::id::paginated::PaginatedID;
 use crate::downloader::id::paginated::Pagination;
+use crate::downloader::report::Traffic;
 use crate::error::Error;
 use crate::error::Result;
+use crate::transport::Transport;
 use crate::writer::Trade;
 use crate::writer::Writer;
 
@@ -18,6 +24,9 @@ use crate::writer::Writer;
 pub struct MexDownloader {
     start: Pagination<DateTime<Utc>, DateTimeID>,
     end: Pagination<DateTime<Utc>, DateTimeID>,
+    follow: Option<Duration>,
+    retries: u32,
+    sleep_millis: u64,
     api: MexAPI,
 }
 
@@ -33,15 +42,40 @@ impl MexDownloader {
             start: Pagination::new(DateTimeID::new(start), 0),
             end: Pagination::new(DateTimeID::new(end), 0),
             api: MexAPI::new(),
+            follow: None,
+            retries: 0,
+            // if we login, the API limit becomes 300/5min
+            // if not, it's 150/5min
+            sleep_millis: (1000 * 60) / 30,
         }
     }
+
+    /// Sends requests through a given transport, e.g. `CassetteTransport` to replay recorded responses.
+    pub fn with_transport(mut self, transport: Arc<dyn Transport>) -> Self {
+        self.api = self.api.with_transport(transport);
+        self
+    }
 }
 
+impl_run_options!(MexDownloader);
+
 impl Downloader for MexDownloader {
     type IDT = Pagination<DateTime<Utc>, DateTimeID>;
     type ID = PaginatedID<DateTime<Utc>, DateTimeID>;
     type RAW = MexGetExecution;
 
+    fn name(&self) -> String {
+        "bitmex".to_owned()
+    }
+
+    fn exchange(&self) -> String {
+        self.api.exchange_name().to_owned()
+    }
+
+    fn symbol(&self) -> String {
+        self.api.product_code().to_owned()
+    }
+
     fn start_id(&self) -> Self::IDT {
         self.start.clone()
     }
@@ -92,20 +126,24 @@ impl Downloader for MexDownloader {
     fn output(&self, u: Vec<Trade>, writer: &mut impl Writer) -> Result<Self::IDT> {
         if let Some(last) = u.last() {
             let last_ts = last.traded_at;
+            let end_ts = *self.end.tuple().0;
             let orig_len = u.len();
             let without_last_count = u.iter().filter(|e| e.traded_at != last_ts).count();
 
             if orig_len == self.limit() && without_last_count == 0 {
                 debug!("increment offset");
-                writer.write(&u).map(|num| {
+                let in_range: Vec<Trade> = u.into_iter().filter(|e| e.traded_at <= end_ts).collect();
+                writer.write(&in_range).map(|num| {
                     info!("wrote {} data", num);
                     debug!("last id: {}", last_ts);
                     // this increments the offset
                     Pagination::new(DateTimeID::new(last_ts), self.limit() as u64)
                 })
             } else {
-                let without_last: Vec<Trade> =
-                    u.into_iter().filter(|e| e.traded_at != last_ts).collect();
+                let without_last: Vec<Trade> = u
+                    .into_iter()
+                    .filter(|e| e.traded_at != last_ts && e.traded_at <= end_ts)
+                    .collect();
 
                 writer.write(without_last.as_slice()).map(|num| {
                     info!("wrote {} data", num);
@@ -115,13 +153,83 @@ impl Downloader for MexDownloader {
             }
         } else {
             warn!("no output");
-            Err(Error::NotFound)
+            Err(Error::EmptyPage)
         }
     }
 
-    /// if we login, the API limit becomes 300/5min
-    /// if not, it's 150/5min
     fn sleep_millis(&self) -> u64 {
-        (1000 * 60) / 30
+        self.sleep_millis
+    }
+
+    fn follow_interval(&self) -> Option<Duration> {
+        self.follow
+    }
+
+    fn max_retries(&self) -> u32 {
+        self.retries
+    }
+
+    fn traffic(&self) -> Option<&Traffic> {
+        Some(self.api.traffic())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::TimeZone;
+    use proptest::prelude::*;
+
+    use crate::downloader::report::StopReason;
+    use crate::recorder::memory::MemoryRecorder;
+    use crate::testing::exchange::{later_trade, trades_in_range};
+    use crate::testing::{assert_conforms_with, FakeExchange, MockWriter};
+    use crate::transport::cassette::fixture;
+
+    use super::*;
+
+    #[test]
+    fn mex_pagination_test() {
+        let cassette = fixture("bitmex");
+        let downloader = MexDownloader::new(
+            Utc.ymd(2019, 1, 1).and_hms(0, 0, 0),
+            Utc.ymd(2019, 1, 1).and_hms_milli(0, 0, 2, 500),
+        )
+        .with_transport(cassette.clone());
+        let mut writer = MockWriter::new();
+        let report = downloader.run(&mut writer, &mut MemoryRecorder::default()).unwrap();
+
+        // trades at the last timestamp of a page are written with the next page
+        assert_eq!(
+            writer.store.iter().map(|t| t.id.as_str()).collect::<Vec<_>>(),
+            vec![
+                "00000000-0000-0000-0000-000000000001",
+                "00000000-0000-0000-0000-000000000002",
+                "00000000-0000-0000-0000-000000000003",
+                "00000000-0000-0000-0000-000000000004",
+            ],
+        );
+        assert_eq!(report.stop_reason, StopReason::RangeExhausted);
+        assert_eq!((report.batches, report.requests, report.statuses[&200]), (2, 2, 2));
+        assert_eq!(cassette.remaining(), 0);
+    }
+
+    proptest! {
+        #![proptest_config(ProptestConfig::with_cases(32))]
+
+        #[test]
+        fn mex_conformance_test((trades, lo, hi) in trades_in_range()) {
+            let expected: Vec<String> = trades
+                .iter()
+                .filter(|t| lo.traded_at <= t.traded_at && t.traded_at <= hi.traded_at)
+                .map(|t| t.id.to_string())
+                .collect();
+            let later = later_trade(&trades);
+            let exchange = Arc::new(FakeExchange::new(trades));
+            let downloader = MexDownloader::new(lo.traded_at, hi.traded_at)
+                .with_sleep_millis(0)
+                .with_transport(exchange.clone());
+            // trades at the latest timestamp are held back until a newer one arrives
+            assert_conforms_with(&downloader, &expected, || exchange.push(later));
+        }
     }
 }
@@ -6,7 +6,7 @@ use crate::downloader::id::DownloaderID;
 use crate::error::Result;
 
 /// An ID implementation by integer numbers.
-#[derive(Debug, Deserialize, Serialize)]
+#[derive(Debug, Clone, Deserialize, Serialize)]
 pub struct OrdID<T> {
     current: T,
 }
@@ -62,7 +62,7 @@ impl<U, T> Display for Pagination<U, T>
 }
 
 /// An wrapper for ID with pagination.
-#[derive(Debug, Deserialize, Serialize)]
+#[derive(Debug, Clone, Deserialize, Serialize)]
 pub struct PaginatedID<U, T> {
     value: Pagination<U, T>,
     phantom: PhantomData<U>,
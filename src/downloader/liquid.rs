@@ -1,3 +1,6 @@
+use std::sync::Arc;
+use std::time::Duration;
+
 use chrono::DateTime;
 use chrono::prelude::*;
 use chrono::Utc;
@@ -5,11 +8,14 @@ use log::{error, info, warn};
 
 use crate::api::liquid::LiquidAPI;
 use crate::api::liquid::LiquidGetExecution;
+use crate::api::ExchangeAPIClient;
 use crate::downloader::Downloader;
 use crate::downloader::id::datetime::DateTimeID;
+use crate::downloader::report::Traffic;
 use crate::error::Error;
 use crate::error::Error::InvalidSide;
 use crate::error::Result;
+use crate::transport::Transport;
 use crate::writer::Trade;
 use crate::writer::Writer;
 
@@ -18,6 +24,9 @@ use crate::writer::Writer;
 pub struct LiquidDownloader {
     start: DateTime<Utc>,
     end: DateTime<Utc>,
+    follow: Option<Duration>,
+    retries: u32,
+    sleep_millis: u64,
     api: LiquidAPI,
 }
 
@@ -33,15 +42,38 @@ impl LiquidDownloader {
             start,
             end,
             api: LiquidAPI::new(),
+            follow: None,
+            retries: 0,
+            sleep_millis: 1100,
         }
     }
+
+    /// Sends requests through a given transport, e.g. `CassetteTransport` to replay recorded responses.
+    pub fn with_transport(mut self, transport: Arc<dyn Transport>) -> Self {
+        self.api = self.api.with_transport(transport);
+        self
+    }
 }
 
+impl_run_options!(LiquidDownloader);
+
 impl Downloader for LiquidDownloader {
     type IDT = DateTime<Utc>;
     type ID = DateTimeID;
     type RAW = LiquidGetExecution;
 
+    fn name(&self) -> String {
+        "liquid".to_owned()
+    }
+
+    fn exchange(&self) -> String {
+        self.api.exchange_name().to_owned()
+    }
+
+    fn symbol(&self) -> String {
+        self.api.product_code().to_owned()
+    }
+
     fn start_id(&self) -> DateTime<Utc> {
         self.start
     }
@@ -96,25 +128,48 @@ impl Downloader for LiquidDownloader {
                 );
                 Err(Error::CannotFetchTradesAccurately)
             } else {
-                writer.write(without_last.as_slice()).map(|num| {
+                // the last page may overrun the end of the range
+                let in_range: Vec<Trade> =
+                    without_last.into_iter().filter(|e| e.traded_at <= self.end).collect();
+                writer.write(in_range.as_slice()).map(|num| {
                     info!("wrote {} data", num);
                     last_ts
                 })
             }
         } else {
             warn!("no output");
-            Err(Error::NotFound)
+            Err(Error::EmptyPage)
         }
     }
 
     fn sleep_millis(&self) -> u64 {
-        1100
+        self.sleep_millis
+    }
+
+    fn follow_interval(&self) -> Option<Duration> {
+        self.follow
+    }
+
+    fn max_retries(&self) -> u32 {
+        self.retries
+    }
+
+    fn traffic(&self) -> Option<&Traffic> {
+        Some(self.api.traffic())
     }
 }
 
 
 #[cfg(test)]
 mod tests {
+    use proptest::prelude::*;
+
+    use crate::downloader::report::StopReason;
+    use crate::recorder::memory::MemoryRecorder;
+    use crate::testing::exchange::{later_trade, trades_in_range};
+    use crate::testing::{assert_conforms_with, FakeExchange, MockWriter};
+    use crate::transport::cassette::fixture;
+
     use super::*;
 
     #[test]
@@ -160,4 +215,47 @@ mod tests {
             assert_eq!(exp, res.unwrap());
         }
     }
+
+    #[test]
+    fn liquid_pagination_test() {
+        let cassette = fixture("liquid");
+        let downloader = LiquidDownloader::new(
+            Utc.ymd(2019, 1, 1).and_hms(0, 0, 0),
+            Utc.ymd(2019, 1, 1).and_hms(0, 0, 10),
+        )
+        .with_transport(cassette.clone());
+        let mut writer = MockWriter::new();
+        let report = downloader.run(&mut writer, &mut MemoryRecorder::default()).unwrap();
+
+        // trades at the last timestamp of a page are written with the next page
+        assert_eq!(
+            writer.store.iter().map(|t| t.id.as_str()).collect::<Vec<_>>(),
+            vec!["101", "102", "103", "104"],
+        );
+        assert_eq!(report.stop_reason, StopReason::RangeExhausted);
+        assert_eq!((report.batches, report.requests, report.statuses[&200]), (2, 2, 2));
+        assert_eq!(cassette.remaining(), 0);
+    }
+
+    proptest! {
+        #![proptest_config(ProptestConfig::with_cases(32))]
+
+        #[test]
+        fn liquid_conformance_test((trades, lo, hi) in trades_in_range()) {
+            // Liquid has timestamps in seconds
+            let (start, end) = (lo.traded_at.timestamp(), hi.traded_at.timestamp());
+            let expected: Vec<String> = trades
+                .iter()
+                .filter(|t| start <= t.traded_at.timestamp() && t.traded_at.timestamp() <= end)
+                .map(|t| t.id.to_string())
+                .collect();
+            let later = later_trade(&trades);
+            let exchange = Arc::new(FakeExchange::new(trades));
+            let downloader = LiquidDownloader::new(Utc.timestamp(start, 0), Utc.timestamp(end, 0))
+                .with_sleep_millis(0)
+                .with_transport(exchange.clone());
+            // trades at the latest timestamp are held back until a newer one arrives
+            assert_conforms_with(&downloader, &expected, || exchange.push(later));
+        }
+    }
 }
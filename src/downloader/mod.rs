@@ -1,18 +1,48 @@
 use std::fmt::Display;
 use std::thread;
-use std::time::Duration;
+use std::time::{Duration, Instant};
 
-use log::{info, trace, warn};
+use chrono::Utc;
 use serde::de::DeserializeOwned;
 use serde::Serialize;
+use tracing::{debug, debug_span, info, info_span, trace, warn};
 
 use crate::downloader::id::DownloaderID;
+use crate::downloader::progress::{Progress, PROGRESS_VERSION};
+use crate::downloader::report::{Batch, RunObserver, RunReport, RunState, StopReason, Traffic};
 use crate::error::Error;
 use crate::error::Result;
 use crate::recorder::ProgressRecorder;
 use crate::writer::Trade;
+use crate::writer::TransactionalWriter;
 use crate::writer::Writer;
 
+/// Implements builders of the options shared by the pre-composed downloaders,
+/// which have `follow`, `retries` and `sleep_millis` fields.
+macro_rules! impl_run_options {
+    ($downloader:ty) => {
+        impl $downloader {
+            /// Keeps polling new trades at a given interval after catching up, instead of returning.
+            pub fn with_follow(mut self, interval: std::time::Duration) -> Self {
+                self.follow = Some(interval);
+                self
+            }
+
+            /// Retries a batch up to `retries` times after a transient error, such as a timeout.
+            pub fn with_retries(mut self, retries: u32) -> Self {
+                self.retries = retries;
+                self
+            }
+
+            /// Sets milli seconds to sleep between pages, e.g. 0 against a fake exchange in tests.
+            pub fn with_sleep_millis(mut self, millis: u64) -> Self {
+                self.sleep_millis = millis;
+                self
+            }
+        }
+    };
+}
+
 /// A downloader for bitFlyer.
 pub mod bf;
 /// A downloader for BitMEX.
@@ -21,20 +51,62 @@ pub mod bitmex;
 pub mod liquid;
 /// ID implementations for composing a Downloader.
 pub mod id;
+/// A format of recorded progress.
+pub mod progress;
+/// A summary of downloading.
+pub mod report;
 
-#[cfg(test)]
-pub(crate) mod mock;
+/// A writer which keeps trades on memory until they are committed with progress.
+#[derive(Debug, Default)]
+struct StagingWriter {
+    trades: Vec<Trade>,
+}
+
+impl Writer for StagingWriter {
+    fn write(&mut self, trades: &[Trade]) -> Result<u64> {
+        self.trades.extend_from_slice(trades);
+        Ok(trades.len() as u64)
+    }
+}
 
 /// An abstraction of downloaders. You can make a new downloader by implementing this trait.
 pub trait Downloader {
     /// A type of element which ID is made up of.
     type IDT: DeserializeOwned + Display;
     /// A type of ID for specifying the downloading point in an API client.
-    type ID: DownloaderID<Self::IDT> + From<Self::IDT> + DeserializeOwned + Serialize;
+    type ID: DownloaderID<Self::IDT> + From<Self::IDT> + DeserializeOwned + Serialize + Clone;
     /// A type of downloaded trade data.
     type RAW;
 
-    /// Returns initial ID from a progress file. If reading is failed, use a given `default` value.
+    /// Returns a name of this downloader, which is recorded with progress.
+    /// This must be stable across builds, since progress recorded with another name is rejected.
+    fn name(&self) -> String;
+    /// Returns a name of the exchange, which is recorded with progress.
+    fn exchange(&self) -> String {
+        String::new()
+    }
+    /// Returns a symbol of the market, which is recorded with progress.
+    fn symbol(&self) -> String {
+        String::new()
+    }
+
+    /// Returns a JSON string to record progress with a given ID.
+    fn progress_json(&self, id: &Self::ID) -> Result<String> {
+        let progress = Progress {
+            version: PROGRESS_VERSION,
+            downloader: self.name(),
+            exchange: self.exchange(),
+            symbol: self.symbol(),
+            range: (self.start_id().to_string(), self.end_id().to_string()),
+            updated_at: Utc::now(),
+            id,
+        };
+        serde_json::to_string(&progress).map_err(Error::from)
+    }
+
+    /// Returns initial ID from a progress recorder. If no progress is recorded yet, use a given `default` value.
+    /// Other errors such as `Error::CorruptProgress` are returned, not to restart from the beginning silently.
+    /// Progress recorded by another downloader is rejected with `Error::ProgressMismatch`.
     fn init_id(&self, default: Self::IDT, recorder: &mut impl ProgressRecorder) -> Result<Self::ID> {
         match recorder.read() {
             Ok(ref s) if s.is_empty() => {
@@ -42,14 +114,17 @@ pub trait Downloader {
                 Ok(Self::ID::from(default))
             }
             Ok(s) => {
-                let id = serde_json::from_str(&s).map_err(Error::ParseJson)?;
+                let progress = Progress::<Self::ID>::parse(&s)?;
+                let range = (self.start_id().to_string(), self.end_id().to_string());
+                progress.validate(&self.name(), &self.exchange(), &self.symbol(), &range)?;
                 trace!("initial value was successfully read from a file");
-                Ok(id)
+                Ok(progress.id)
             }
-            Err(e) => {
-                warn!("cannot read from a file: {}", e);
+            Err(Error::NoProgress) => {
+                info!("no progress yet, start with the default value: {}", default);
                 Ok(Self::ID::from(default))
             }
+            Err(e) => Err(e),
         }
     }
 
@@ -67,35 +142,250 @@ pub trait Downloader {
     fn convert(&self, v: &Self::RAW) -> Result<Trade>;
     /// Writes trade data to somewhere with a given writer.
     /// This must return an id for the next iteration, and this will be recorded on a progress file.
+    /// Trades beyond the end of the range must not be written, even if a page overruns it.
     fn output(&self, u: Vec<Trade>, writer: &mut impl Writer) -> Result<Self::IDT>;
 
     /// Returns milli seconds to sleep between fetching processes.
     fn sleep_millis(&self) -> u64;
 
+    /// Returns an interval to poll new trades after catching up with the latest data.
+    /// If this is `None`, `run` returns when caught up.
+    fn follow_interval(&self) -> Option<Duration> {
+        None
+    }
+
+    /// Returns how many times a batch is retried after a retryable error (see `Error::is_retryable`)
+    /// before `run` returns the error. The default is 0.
+    fn max_retries(&self) -> u32 {
+        0
+    }
+
+    /// Returns counters of requests to the exchange, which are reported by `run`, if any.
+    fn traffic(&self) -> Option<&Traffic> {
+        None
+    }
+
     /// Executes downloading.
-    fn run(&self, writer: &mut impl Writer, recorder: &mut impl ProgressRecorder) -> Result<()> {
-        let mut init_id = self.init_id(self.start_id(), recorder)?;
-        info!("start from {}", init_id.current());
-        let end_id_value = self.end_id();
-        info!("run to {}", end_id_value);
-
-        while self.continue_condition(&init_id.current(), &end_id_value) {
-            self.fetch(init_id.current())
-                .and_then(|v| v.iter().map(|t| self.convert(&t)).collect())
-                .and_then(|v| self.output(v, writer))
-                .and_then(|next_id| {
-                    init_id
-                        .update(next_id)
-                        .and_then(|_| serde_json::to_string(&init_id).map_err(Error::from))
-                        .and_then(|json| recorder.out(&json))
-                })
-                .map(|_| {
-                    let millis = self.sleep_millis();
-                    trace!("sleep {} ms", millis);
-                    thread::sleep(Duration::from_millis(millis));
-                })?
+    /// This returns a report when the range is exhausted, or when no more trades are available.
+    fn run(&self, writer: &mut impl Writer, recorder: &mut impl ProgressRecorder) -> Result<RunReport> {
+        self.run_with_observer(writer, recorder, |_: &RunReport| ())
+    }
+
+    /// Executes downloading like `run`, and calls `observer` after each batch.
+    fn run_with_observer(
+        &self,
+        writer: &mut impl Writer,
+        recorder: &mut impl ProgressRecorder,
+        mut observer: impl RunObserver,
+    ) -> Result<RunReport> {
+        let init_id = self.init_id(self.start_id(), recorder);
+        run_loop(self, init_id, &mut observer, |id| {
+            let raw = debug_span!("fetch").in_scope(|| self.fetch(id.current()))?;
+            let trades = debug_span!("convert", page_size = raw.len())
+                .in_scope(|| raw.iter().map(|t| self.convert(t)).collect::<Result<Vec<_>>>())?;
+            let mut counter = CountingWriter {
+                inner: &mut *writer,
+                batch: Batch::default(),
+            };
+            counter.batch.fetched = trades.len() as u64;
+            let next_id = debug_span!("output").in_scope(|| self.output(trades, &mut counter))?;
+            debug_span!("record").in_scope(|| -> Result<()> {
+                // `id` stays as it is until progress is recorded, so a retried batch starts from the same point
+                let mut next = id.clone();
+                next.update(next_id)?;
+                recorder.out(&self.progress_json(&next)?)?;
+                *id = next;
+                Ok(())
+            })?;
+            Ok(counter.batch)
+        })
+    }
+
+    /// Executes downloading with a writer which records progress by itself.
+    /// Each batch of trades is committed together with the progress after it,
+    /// so a crash never leaves written trades without progress, or vice versa.
+    fn run_transactional(&self, writer: &mut impl TransactionalWriter) -> Result<RunReport> {
+        self.run_transactional_with_observer(writer, |_: &RunReport| ())
+    }
+
+    /// Executes downloading like `run_transactional`, and calls `observer` after each batch.
+    fn run_transactional_with_observer(
+        &self,
+        writer: &mut impl TransactionalWriter,
+        mut observer: impl RunObserver,
+    ) -> Result<RunReport> {
+        let init_id = self.init_id(self.start_id(), writer);
+        run_loop(self, init_id, &mut observer, |id| {
+            let raw = debug_span!("fetch").in_scope(|| self.fetch(id.current()))?;
+            let trades = debug_span!("convert", page_size = raw.len())
+                .in_scope(|| raw.iter().map(|t| self.convert(t)).collect::<Result<Vec<_>>>())?;
+            let mut staging = StagingWriter::default();
+            let mut batch = Batch {
+                fetched: trades.len() as u64,
+                ..Batch::default()
+            };
+            let next_id = debug_span!("output").in_scope(|| self.output(trades, &mut staging))?;
+            let num = debug_span!("record").in_scope(|| -> Result<u64> {
+                // `id` stays as it is until progress is recorded, so a retried batch starts from the same point
+                let mut next = id.clone();
+                next.update(next_id)?;
+                let num = writer.write_with_progress(&staging.trades, &self.progress_json(&next)?)?;
+                *id = next;
+                Ok(num)
+            })?;
+            info!("committed {} data", num);
+            batch.add(&staging.trades, num);
+            Ok(batch)
+        })
+    }
+}
+
+/// Runs `step` repeatedly from `init`, and notifies `observer` of how it finishes.
+fn run_loop<D, F>(
+    downloader: &D,
+    init: Result<D::ID>,
+    observer: &mut impl RunObserver,
+    step: F,
+) -> Result<RunReport>
+    where
+        D: Downloader + ?Sized,
+        F: FnMut(&mut D::ID) -> Result<Batch>,
+{
+    let span = info_span!(
+        "run",
+        downloader = %downloader.name(),
+        exchange = %downloader.exchange(),
+        symbol = %downloader.symbol(),
+    );
+    let _enter = span.enter();
+    let result = init.and_then(|id| run_steps(downloader, id, observer, step));
+    match result {
+        Ok(_) => observer.on_state(RunState::Finished),
+        Err(ref e) => {
+            observer.on_error(e);
+            observer.on_state(RunState::Failed);
+        }
+    }
+    result
+}
+
+/// Runs `step` repeatedly from `id`, until the range is exhausted or no more trades are available.
+///
+/// It is regarded as caught up when a page is empty, or when the ID doesn't advance.
+/// IDs are compared by their `Display` representations.
+fn run_steps<D, F>(
+    downloader: &D,
+    mut id: D::ID,
+    observer: &mut impl RunObserver,
+    mut step: F,
+) -> Result<RunReport>
+    where
+        D: Downloader + ?Sized,
+        F: FnMut(&mut D::ID) -> Result<Batch>,
+{
+    info!("start from {}", id.current());
+    let end_id_value = downloader.end_id();
+    info!("run to {}", end_id_value);
+    let started = Instant::now();
+    let traffic_at_start = downloader
+        .traffic()
+        .map(|t| (t.requests(), t.bytes(), t.statuses()));
+    let mut report = RunReport::new();
+    let mut retries = 0;
+
+    while downloader.continue_condition(id.current(), &end_id_value) {
+        if !observer.proceed() {
+            info!("stopped at {}", id.current());
+            report.stop_reason = StopReason::Stopped;
+            break;
+        }
+        observer.on_state(RunState::Running);
+        let before = id.current().to_string();
+        let batch_started = Instant::now();
+        let result = debug_span!("batch", id = %before).in_scope(|| step(&mut id));
+
+        report.elapsed = started.elapsed();
+        if let (Some(t), Some((requests, bytes, statuses))) = (downloader.traffic(), &traffic_at_start) {
+            report.requests = t.requests() - requests;
+            report.bytes = t.bytes() - bytes;
+            report.statuses = t.statuses();
+            for (status, n) in report.statuses.iter_mut() {
+                *n -= statuses.get(status).cloned().unwrap_or(0);
+            }
         }
 
-        Ok(())
+        let state = match result {
+            Ok(batch) => {
+                debug!(
+                    id = %before,
+                    page_size = batch.fetched,
+                    written = batch.written,
+                    latency_ms = batch_started.elapsed().as_millis() as u64,
+                    "batch done"
+                );
+                retries = 0;
+                report.add(&batch);
+                report.final_id = id.current().to_string();
+                observer.on_batch(&report);
+                if report.final_id == before {
+                    RunState::CaughtUp
+                } else {
+                    RunState::Sleeping
+                }
+            }
+            Err(Error::EmptyPage) => {
+                observer.on_failed_batch(&report);
+                RunState::CaughtUp
+            }
+            Err(ref e) if e.is_retryable() && retries < downloader.max_retries() => {
+                retries += 1;
+                report.retries += 1;
+                warn!("retry {}/{}: {}", retries, downloader.max_retries(), e);
+                observer.on_failed_batch(&report);
+                observer.on_error(e);
+                RunState::Retrying
+            }
+            Err(e) => {
+                observer.on_failed_batch(&report);
+                return Err(e);
+            }
+        };
+
+        let wait = if state == RunState::CaughtUp {
+            match downloader.follow_interval() {
+                Some(interval) => {
+                    info!("caught up at {}, wait for new trades", id.current());
+                    interval
+                }
+                None => {
+                    info!("caught up at {}", id.current());
+                    report.stop_reason = StopReason::CaughtUp;
+                    break;
+                }
+            }
+        } else {
+            Duration::from_millis(downloader.sleep_millis())
+        };
+        observer.on_state(state);
+        trace!("sleep {:?}", wait);
+        thread::sleep(wait);
+    }
+
+    report.elapsed = started.elapsed();
+    report.final_id = id.current().to_string();
+    Ok(report)
+}
+
+/// A writer which counts trades passed to an inner writer.
+struct CountingWriter<'a, W> {
+    inner: &'a mut W,
+    batch: Batch,
+}
+
+impl<'a, W: Writer> Writer for CountingWriter<'a, W> {
+    fn write(&mut self, trades: &[Trade]) -> Result<u64> {
+        let num = self.inner.write(trades)?;
+        self.batch.add(trades, num);
+        Ok(num)
     }
 }
@@ -0,0 +1,132 @@
+use chrono::{DateTime, TimeZone, Utc};
+use log::warn;
+use serde::de::DeserializeOwned;
+use serde_derive::{Deserialize, Serialize};
+
+use crate::error::Error;
+use crate::error::Result;
+
+/// The current version of the progress format.
+pub const PROGRESS_VERSION: u32 = 1;
+
+/// Recorded progress of a downloader, with information to check where it comes from.
+///
+/// Progress written before this format, which is a bare ID such as `{"current":18}`,
+/// is read as version 0 and upgraded at the next write.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Progress<ID> {
+    /// A version of this format.
+    pub version: u32,
+    /// A name of the downloader. See `Downloader::name`.
+    pub downloader: String,
+    /// A name of the exchange.
+    pub exchange: String,
+    /// A symbol of the market.
+    pub symbol: String,
+    /// The starting and ending IDs of the downloader, formatted by `Display`.
+    pub range: (String, String),
+    /// When the progress was recorded.
+    pub updated_at: DateTime<Utc>,
+    /// An ID for the next fetch.
+    pub id: ID,
+}
+
+impl<ID: DeserializeOwned> Progress<ID> {
+    /// Parses recorded progress, including a bare ID of the legacy format.
+    pub fn parse(s: &str) -> Result<Self> {
+        let value: serde_json::Value = serde_json::from_str(s)?;
+        let version = match value.get("version") {
+            Some(v) => v
+                .as_u64()
+                .ok_or_else(|| Error::CorruptProgress(format!("invalid version: {}", v)))?,
+            None => {
+                let id = serde_json::from_value(value)
+                    .map_err(|e| Error::CorruptProgress(e.to_string()))?;
+                return Ok(Progress {
+                    version: 0,
+                    downloader: String::new(),
+                    exchange: String::new(),
+                    symbol: String::new(),
+                    range: (String::new(), String::new()),
+                    updated_at: Utc.timestamp(0, 0),
+                    id,
+                });
+            }
+        };
+        if version > u64::from(PROGRESS_VERSION) {
+            return Err(Error::ProgressMismatch(format!(
+                "version {} is newer than {}",
+                version, PROGRESS_VERSION
+            )));
+        }
+        serde_json::from_value(value).map_err(|e| Error::CorruptProgress(e.to_string()))
+    }
+}
+
+impl<ID> Progress<ID> {
+    /// Checks that this progress was recorded by a downloader with given properties.
+    /// A different range is accepted with a warning, and so is the legacy format, which cannot be checked.
+    pub fn validate(
+        &self,
+        downloader: &str,
+        exchange: &str,
+        symbol: &str,
+        range: &(String, String),
+    ) -> Result<()> {
+        if self.version == 0 {
+            warn!("progress is in the legacy format, so its owner is not checked");
+            return Ok(());
+        }
+        let expected = (downloader, exchange, symbol);
+        let actual = (self.downloader.as_str(), self.exchange.as_str(), self.symbol.as_str());
+        if expected != actual {
+            return Err(Error::ProgressMismatch(format!(
+                "recorded by {:?}, but this is {:?}",
+                actual, expected
+            )));
+        }
+        if &self.range != range {
+            warn!("the range has changed from {:?} to {:?}", self.range, range);
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::downloader::id::num::OrdID;
+    use crate::downloader::id::DownloaderID;
+
+    use super::*;
+
+    #[test]
+    fn progress_parse_test() {
+        let range = ("10".to_owned(), "16".to_owned());
+
+        let legacy = Progress::<OrdID<u32>>::parse(r#"{"current":18}"#).unwrap();
+        assert_eq!((legacy.version, *legacy.id.current()), (0, 18));
+        assert!(legacy.validate("mock", "", "", &range).is_ok());
+
+        let json = r#"{"version":1,"downloader":"mock","exchange":"ex","symbol":"BTC","range":["10","16"],"updated_at":"2019-01-01T00:00:00Z","id":{"current":12}}"#;
+        let progress = Progress::<OrdID<u32>>::parse(json).unwrap();
+        assert_eq!(*progress.id.current(), 12);
+        assert!(progress.validate("mock", "ex", "BTC", &range).is_ok());
+        match progress.validate("other", "ex", "BTC", &range) {
+            Err(Error::ProgressMismatch(_)) => (),
+            r => panic!("unexpected: {:?}", r),
+        }
+        match progress.validate("mock", "ex", "ETH", &range) {
+            Err(Error::ProgressMismatch(_)) => (),
+            r => panic!("unexpected: {:?}", r),
+        }
+
+        match Progress::<OrdID<u32>>::parse(&json.replace(r#""version":1"#, r#""version":2"#)) {
+            Err(Error::ProgressMismatch(_)) => (),
+            r => panic!("unexpected: {:?}", r),
+        }
+        match Progress::<OrdID<u32>>::parse(&json.replace("current", "next")) {
+            Err(Error::CorruptProgress(_)) => (),
+            r => panic!("unexpected: {:?}", r),
+        }
+    }
+}
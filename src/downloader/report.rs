@@ -0,0 +1,250 @@
+use std::collections::BTreeMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::time::Duration;
+
+use chrono::{DateTime, Utc};
+use serde_derive::Serialize;
+
+use crate::error::Error;
+use crate::writer::Trade;
+
+/// A reason why `Downloader::run` stopped.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StopReason {
+    /// The current ID reached the end of the range.
+    RangeExhausted,
+    /// No more trades were available before the end of the range,
+    /// e.g. the end is in the future.
+    CaughtUp,
+    /// `RunObserver::proceed` returned false.
+    Stopped,
+}
+
+/// A state of `Downloader::run`, notified to `RunObserver::on_state`.
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum RunState {
+    /// Fetching and writing a batch.
+    Running,
+    /// Sleeping between batches.
+    Sleeping,
+    /// Sleeping before retrying a batch after a transient error.
+    Retrying,
+    /// Waiting for new trades after catching up. See `Downloader::follow_interval`.
+    CaughtUp,
+    /// Finished without errors.
+    Finished,
+    /// Finished with an error, which is passed to `RunObserver::on_error`.
+    Failed,
+}
+
+/// A summary of `Downloader::run`. This is also passed to `RunObserver` after each batch,
+/// with the numbers so far.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RunReport {
+    /// Why downloading stopped. This is `RangeExhausted` while running.
+    pub stop_reason: StopReason,
+    /// The number of batches which were written and recorded.
+    pub batches: u64,
+    /// The number of requests to the exchange. This is 0 if the downloader doesn't count them.
+    pub requests: u64,
+    /// The number of bytes of response bodies. This is 0 if the downloader doesn't count them.
+    pub bytes: u64,
+    /// The number of responses by HTTP status codes.
+    pub statuses: BTreeMap<u16, u64>,
+    /// The number of trades fetched and converted.
+    pub fetched: u64,
+    /// The number of trades written, as reported by the writer.
+    pub written: u64,
+    /// The number of trades passed to the writer but not written, e.g. duplicates
+    /// ignored by `InsertStrategy::InsertIgnore`.
+    pub deduplicated: u64,
+    /// The number of batches retried after transient errors. See `Downloader::max_retries`.
+    pub retries: u64,
+    /// Time elapsed since the start.
+    pub elapsed: Duration,
+    /// The earliest timestamp of trades passed to the writer.
+    pub first_traded_at: Option<DateTime<Utc>>,
+    /// The latest timestamp of trades passed to the writer.
+    pub last_traded_at: Option<DateTime<Utc>>,
+    /// The last recorded ID, formatted by `Display`.
+    pub final_id: String,
+}
+
+impl RunReport {
+    pub(crate) fn new() -> Self {
+        RunReport {
+            stop_reason: StopReason::RangeExhausted,
+            batches: 0,
+            requests: 0,
+            bytes: 0,
+            statuses: BTreeMap::new(),
+            fetched: 0,
+            written: 0,
+            deduplicated: 0,
+            retries: 0,
+            elapsed: Duration::default(),
+            first_traded_at: None,
+            last_traded_at: None,
+            final_id: String::new(),
+        }
+    }
+
+    /// Adds numbers of a batch.
+    pub(crate) fn add(&mut self, batch: &Batch) {
+        self.batches += 1;
+        self.fetched += batch.fetched;
+        self.written += batch.written;
+        self.deduplicated += batch.passed.saturating_sub(batch.written);
+        self.first_traded_at = min_option(self.first_traded_at, batch.first_traded_at);
+        self.last_traded_at = max_option(self.last_traded_at, batch.last_traded_at);
+    }
+}
+
+fn min_option<T: Ord>(a: Option<T>, b: Option<T>) -> Option<T> {
+    match (a, b) {
+        (Some(a), Some(b)) => Some(std::cmp::min(a, b)),
+        (a, b) => a.or(b),
+    }
+}
+
+fn max_option<T: Ord>(a: Option<T>, b: Option<T>) -> Option<T> {
+    match (a, b) {
+        (Some(a), Some(b)) => Some(std::cmp::max(a, b)),
+        (a, b) => a.or(b),
+    }
+}
+
+/// Numbers of a single batch.
+#[derive(Debug, Default)]
+pub(crate) struct Batch {
+    /// Trades fetched and converted.
+    pub fetched: u64,
+    /// Trades passed to the writer.
+    pub passed: u64,
+    /// Trades written, as reported by the writer.
+    pub written: u64,
+    pub first_traded_at: Option<DateTime<Utc>>,
+    pub last_traded_at: Option<DateTime<Utc>>,
+}
+
+impl Batch {
+    /// Adds trades passed to the writer, and the number of written ones.
+    pub fn add(&mut self, trades: &[Trade], written: u64) {
+        self.passed += trades.len() as u64;
+        self.written += written;
+        for t in trades {
+            self.first_traded_at = min_option(self.first_traded_at, Some(t.traded_at));
+            self.last_traded_at = max_option(self.last_traded_at, Some(t.traded_at));
+        }
+    }
+}
+
+/// Counters of requests to an exchange, shared by an API client and `Downloader::run`.
+#[derive(Debug, Default)]
+pub struct Traffic {
+    requests: AtomicU64,
+    bytes: AtomicU64,
+    statuses: Mutex<BTreeMap<u16, u64>>,
+}
+
+impl Traffic {
+    /// Returns the number of requests so far.
+    pub fn requests(&self) -> u64 {
+        self.requests.load(Ordering::Relaxed)
+    }
+
+    /// Returns the number of bytes of response bodies so far.
+    pub fn bytes(&self) -> u64 {
+        self.bytes.load(Ordering::Relaxed)
+    }
+
+    /// Returns the number of responses by HTTP status codes so far.
+    pub fn statuses(&self) -> BTreeMap<u16, u64> {
+        self.statuses.lock().map(|s| s.clone()).unwrap_or_default()
+    }
+
+    pub(crate) fn add_request(&self) {
+        self.requests.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub(crate) fn add_bytes(&self, bytes: u64) {
+        self.bytes.fetch_add(bytes, Ordering::Relaxed);
+    }
+
+    pub(crate) fn add_status(&self, status: u16) {
+        if let Ok(mut statuses) = self.statuses.lock() {
+            *statuses.entry(status).or_insert(0) += 1;
+        }
+    }
+}
+
+/// A hook invoked after each batch of `Downloader::run_with_observer`,
+/// e.g. to drive a progress bar. Closures taking `&RunReport` implement this.
+pub trait RunObserver {
+    /// Receives a report of numbers so far.
+    fn on_batch(&mut self, report: &RunReport);
+
+    /// Receives a report of numbers so far after a batch fails, e.g. to count its requests.
+    /// This is followed by `on_error` unless the page is empty.
+    fn on_failed_batch(&mut self, _report: &RunReport) {}
+
+    /// Receives a new state of the run.
+    fn on_state(&mut self, _state: RunState) {}
+
+    /// Receives an error which the run returns, or which is retried.
+    fn on_error(&mut self, _error: &Error) {}
+
+    /// Called before each batch. Returns false to stop the run with `StopReason::Stopped`.
+    /// This may block to pause the run.
+    fn proceed(&mut self) -> bool {
+        true
+    }
+}
+
+impl<F: FnMut(&RunReport)> RunObserver for F {
+    fn on_batch(&mut self, report: &RunReport) {
+        self(report)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::TimeZone;
+
+    use super::*;
+
+    #[test]
+    fn run_report_add_test() {
+        let trade = |id: i64| Trade {
+            id: id.to_string(),
+            traded_at: Utc.timestamp(id, 0),
+            quantity: 1.0,
+            price: 1.0,
+        };
+        let mut report = RunReport::new();
+
+        let mut batch = Batch {
+            fetched: 3,
+            ..Batch::default()
+        };
+        batch.add(&[trade(5), trade(3)], 2);
+        batch.add(&[trade(4)], 0);
+        report.add(&batch);
+
+        let mut batch = Batch {
+            fetched: 1,
+            ..Batch::default()
+        };
+        batch.add(&[trade(9)], 1);
+        report.add(&batch);
+
+        assert_eq!(
+            (report.batches, report.fetched, report.written, report.deduplicated),
+            (2, 4, 3, 1),
+        );
+        assert_eq!(report.first_traded_at, Some(Utc.timestamp(3, 0)));
+        assert_eq!(report.last_traded_at, Some(Utc.timestamp(9, 0)));
+    }
+}
@@ -1,14 +1,50 @@
-use std::error::Error as _Error;
 use std::fmt;
 
 /// An alias for representing a downloading result easily.
 pub type Result<T> = std::result::Result<T, Error>;
 
+/// Categories of `Error`, to decide how to handle it without matching every variant.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ErrorKind {
+    /// Failures on the network, such as a timeout or a refused connection.
+    Transport,
+    /// A response with an unsuccessful HTTP status.
+    HttpStatus,
+    /// An error reported by an exchange API in its response body.
+    Exchange,
+    /// Failures to decode or convert data.
+    Decode,
+    /// Failures in writers and recorders, such as databases and files.
+    Storage,
+    /// Problems of recorded progress.
+    Progress,
+    /// Conditions of downloading itself.
+    Domain,
+}
+
 /// Errors encountered by the downloading.
 #[derive(Debug)]
 pub enum Error {
     /// An `reqwest::Error` that occurred while fetching from an external API.
     Reqwest(reqwest::Error),
+    /// An error that occurred when an external API responded with an unsuccessful status.
+    HttpStatus {
+        /// The HTTP status code.
+        status: u16,
+        /// The response body.
+        body: String,
+    },
+    /// An error that occurred when an exchange API reported an error in its response body.
+    Exchange {
+        /// A name of the exchange.
+        exchange: String,
+        /// The HTTP status code.
+        status: u16,
+        /// An error code or name given by the exchange, if any.
+        code: Option<String>,
+        /// An error message given by the exchange.
+        message: String,
+    },
     /// An `std::num::ParseFloatError` that occurred while converting a value to a Float value.
     ParseFloat(std::num::ParseFloatError),
     /// An `std::num::ParseIntError` that occurred while converting a value to a Int value.
@@ -16,9 +52,9 @@ pub enum Error {
     /// An `mysql::error::Error` that occurred in general MySQL processing.
     MySql(Box<mysql::error::Error>),
     /// An error that occurred when parameter mappings are invalid.
-    MySqlMissingNamedParameter(Box<std::error::Error>),
-    /// An error that occurred when fetched data is empty.
-    NotFound,
+    MySqlMissingNamedParameter(Box<dyn std::error::Error>),
+    /// An error that occurred when a fetched page has no trades.
+    EmptyPage,
     /// An `io::Error` that occurred while trying to read or write to a network stream.
     IO(std::io::Error),
     /// An `chrono::format::ParseError` that occurred while converting a value to a chrono::DateTime.
@@ -30,40 +66,282 @@ pub enum Error {
     ParseJson(serde_json::error::Error),
     /// An error that occurred when the order/position side is invalid.
     InvalidSide(String),
+    /// An error that occurred when a table was migrated by a newer version of the definition.
+    NewerSchemaVersion {
+        /// A name of the table.
+        table: String,
+        /// A version recorded in the database.
+        found: u32,
+        /// The latest version this program knows.
+        supported: u32,
+    },
+    /// An error that occurred when no progress has been recorded yet.
+    NoProgress,
+    /// An error that occurred when recorded progress is broken. This has a reason.
+    CorruptProgress(String),
+    /// An error that occurred when recorded progress belongs to another downloader. This has a reason.
+    ProgressMismatch(String),
+    /// An error that occurred when a job is locked by another instance. This has the job name.
+    Locked(String),
+    /// An error that occurred in a transport other than reqwest,
+    /// e.g. a request which is not recorded in a cassette. This has a reason.
+    Transport(String),
+    /// An `arrow::error::ArrowError` that occurred while building Arrow record batches.
+    #[cfg(feature = "parquet")]
+    Arrow(arrow::error::ArrowError),
+    /// A `parquet::errors::ParquetError` that occurred while writing Parquet files.
+    #[cfg(feature = "parquet")]
+    Parquet(parquet::errors::ParquetError),
+    /// A `postgres::Error` that occurred in general PostgreSQL processing.
+    #[cfg(feature = "postgres")]
+    Postgres(postgres::Error),
+    /// A `rusqlite::Error` that occurred in general SQLite processing.
+    #[cfg(feature = "sqlite")]
+    SQLite(rusqlite::Error),
+    /// A `redis::RedisError` that occurred in general Redis processing.
+    #[cfg(feature = "redis")]
+    Redis(redis::RedisError),
+}
+
+/// Whether a request with a given status may succeed later, e.g. after a rate limit.
+fn is_retryable_status(status: u16) -> bool {
+    status == 408 || status == 425 || status == 429 || status >= 500
+}
+
+/// Whether a request with a given status is rejected for its credentials.
+fn is_fatal_status(status: u16) -> bool {
+    status == 401 || status == 403
+}
+
+/// Whether a MySQL error means the connection is lost, so a new connection may succeed.
+fn is_mysql_connection_error(e: &mysql::error::Error) -> bool {
+    use mysql::error::{DriverError, Error as MySqlError};
+    match *e {
+        MySqlError::IoError(_) => true,
+        MySqlError::DriverError(DriverError::CouldNotConnect(_))
+        | MySqlError::DriverError(DriverError::PacketOutOfSync) => true,
+        // server shutdown, aborted connection, network errors and killed connection
+        MySqlError::MySqlError(ref e) => [1053, 1152, 1158, 1159, 1160, 1161, 1927].contains(&e.code),
+        _ => false,
+    }
+}
+
+/// Whether an I/O error is transient, e.g. a timeout or a reset connection,
+/// unlike a full disk or a denied permission.
+fn is_io_retryable(e: &std::io::Error) -> bool {
+    use std::io::ErrorKind;
+    matches!(
+        e.kind(),
+        ErrorKind::TimedOut
+            | ErrorKind::Interrupted
+            | ErrorKind::ConnectionReset
+            | ErrorKind::ConnectionAborted
+            | ErrorKind::ConnectionRefused
+            | ErrorKind::WouldBlock
+    )
+}
+
+/// Whether a PostgreSQL error may disappear by retrying later: a lost connection (class 08),
+/// a serialization failure, a deadlock or too many connections.
+#[cfg(feature = "postgres")]
+fn is_postgres_retryable(e: &postgres::Error) -> bool {
+    if e.is_closed() {
+        return true;
+    }
+    match e.code() {
+        Some(state) => {
+            let code = state.code();
+            code.starts_with("08") || ["40001", "40P01", "53300"].contains(&code)
+        }
+        None => false,
+    }
+}
+
+/// Whether a SQLite error may disappear by retrying later, i.e. the database is busy or locked.
+#[cfg(feature = "sqlite")]
+fn is_sqlite_retryable(e: &rusqlite::Error) -> bool {
+    match *e {
+        rusqlite::Error::SqliteFailure(ref e, _) => {
+            e.code == rusqlite::ErrorCode::DatabaseBusy || e.code == rusqlite::ErrorCode::DatabaseLocked
+        }
+        _ => false,
+    }
+}
+
+/// Whether a MySQL error may disappear by retrying later, e.g. a lost connection or a deadlock.
+fn is_mysql_retryable(e: &mysql::error::Error) -> bool {
+    use mysql::error::{DriverError, Error as MySqlError};
+    if is_mysql_connection_error(e) {
+        return true;
+    }
+    match *e {
+        MySqlError::DriverError(DriverError::Timeout) => true,
+        // too many connections, lock wait timeout and deadlock
+        MySqlError::MySqlError(ref e) => [1040, 1205, 1213].contains(&e.code),
+        _ => false,
+    }
+}
+
+impl Error {
+    /// Returns the category of this error.
+    pub fn kind(&self) -> ErrorKind {
+        match *self {
+            Error::Reqwest(ref e) if e.is_serialization() => ErrorKind::Decode,
+            Error::Reqwest(ref e) if e.status().is_some() => ErrorKind::HttpStatus,
+            Error::Reqwest(_) | Error::Transport(_) => ErrorKind::Transport,
+            Error::HttpStatus { .. } => ErrorKind::HttpStatus,
+            Error::Exchange { .. } => ErrorKind::Exchange,
+            Error::ParseFloat(_)
+            | Error::ParseInt(_)
+            | Error::ChronoParse(_)
+            | Error::ParseJson(_)
+            | Error::InvalidSide(_) => ErrorKind::Decode,
+            Error::MySql(_)
+            | Error::MySqlMissingNamedParameter(_)
+            | Error::IO(_)
+            | Error::NewerSchemaVersion { .. } => ErrorKind::Storage,
+            #[cfg(feature = "parquet")]
+            Error::Arrow(_) | Error::Parquet(_) => ErrorKind::Storage,
+            #[cfg(feature = "postgres")]
+            Error::Postgres(_) => ErrorKind::Storage,
+            #[cfg(feature = "sqlite")]
+            Error::SQLite(_) => ErrorKind::Storage,
+            #[cfg(feature = "redis")]
+            Error::Redis(_) => ErrorKind::Storage,
+            Error::NoProgress
+            | Error::CorruptProgress(_)
+            | Error::ProgressMismatch(_)
+            | Error::Locked(_) => ErrorKind::Progress,
+            Error::EmptyPage | Error::CannotFetchTradesAccurately => ErrorKind::Domain,
+        }
+    }
+
+    /// Returns true if a connection to a database is lost, and reconnecting may help.
+    pub fn is_connection_lost(&self) -> bool {
+        match *self {
+            Error::MySql(ref e) => is_mysql_connection_error(e),
+            _ => false,
+        }
+    }
+
+    /// Returns true if the same operation may succeed by retrying later,
+    /// e.g. network failures, rate limits, lost database connections and held locks.
+    /// `EmptyPage` is not, since it means there are no new trades yet.
+    pub fn is_retryable(&self) -> bool {
+        match *self {
+            Error::Reqwest(ref e) => match e.status() {
+                Some(status) => is_retryable_status(status.as_u16()),
+                None => !e.is_serialization(),
+            },
+            Error::HttpStatus { status, .. } | Error::Exchange { status, .. } => {
+                is_retryable_status(status)
+            }
+            Error::MySql(ref e) => is_mysql_retryable(e),
+            Error::IO(ref e) => is_io_retryable(e),
+            #[cfg(feature = "postgres")]
+            Error::Postgres(ref e) => is_postgres_retryable(e),
+            #[cfg(feature = "sqlite")]
+            Error::SQLite(ref e) => is_sqlite_retryable(e),
+            #[cfg(feature = "redis")]
+            Error::Redis(ref e) => e.is_io_error() || e.is_timeout() || e.is_connection_dropped(),
+            Error::Locked(_) => true,
+            _ => false,
+        }
+    }
+
+    /// Returns true if retrying never helps until a configuration or data is fixed,
+    /// e.g. corrupt progress, rejected credentials and incompatible tables.
+    pub fn is_fatal(&self) -> bool {
+        match *self {
+            Error::Reqwest(ref e) => e.status().is_some_and(|s| is_fatal_status(s.as_u16())),
+            Error::HttpStatus { status, .. } | Error::Exchange { status, .. } => {
+                is_fatal_status(status)
+            }
+            Error::MySqlMissingNamedParameter(_)
+            | Error::NewerSchemaVersion { .. }
+            | Error::CorruptProgress(_)
+            | Error::ProgressMismatch(_)
+            | Error::Transport(_)
+            | Error::CannotFetchTradesAccurately => true,
+            _ => false,
+        }
+    }
 }
 
 impl fmt::Display for Error {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match *self {
             Error::Reqwest(ref e) => e.fmt(f),
+            Error::HttpStatus { status, ref body } => write!(f, "HTTP status {}: {}", status, body),
+            Error::Exchange {
+                ref exchange,
+                status,
+                ref code,
+                ref message,
+            } => match *code {
+                Some(ref code) => write!(f, "{} ({}, {}): {}", exchange, status, code, message),
+                None => write!(f, "{} ({}): {}", exchange, status, message),
+            },
             Error::ParseFloat(ref e) => e.fmt(f),
             Error::ParseInt(ref e) => e.fmt(f),
             Error::MySql(ref e) => e.fmt(f),
             Error::MySqlMissingNamedParameter(ref e) => e.fmt(f),
+            Error::EmptyPage => f.write_str("No trades in a page"),
             Error::IO(ref e) => e.fmt(f),
             Error::ChronoParse(ref e) => e.fmt(f),
+            Error::CannotFetchTradesAccurately => f.write_str("Cannot fetch from API"),
             Error::ParseJson(ref e) => e.fmt(f),
             Error::InvalidSide(ref s) => write!(f, "Invalid side: {}", s),
-
-            ref e => f.write_str(e.description()),
+            Error::NewerSchemaVersion {
+                ref table,
+                found,
+                supported,
+            } => write!(
+                f,
+                "Schema version of {} is {}, but only {} is supported",
+                table, found, supported
+            ),
+            Error::NoProgress => f.write_str("No progress recorded"),
+            Error::CorruptProgress(ref s) => write!(f, "Corrupt progress: {}", s),
+            Error::ProgressMismatch(ref s) => write!(f, "Progress mismatch: {}", s),
+            Error::Locked(ref s) => write!(f, "{} is locked by another instance", s),
+            Error::Transport(ref s) => write!(f, "Transport error: {}", s),
+            #[cfg(feature = "parquet")]
+            Error::Arrow(ref e) => e.fmt(f),
+            #[cfg(feature = "parquet")]
+            Error::Parquet(ref e) => e.fmt(f),
+            #[cfg(feature = "postgres")]
+            Error::Postgres(ref e) => e.fmt(f),
+            #[cfg(feature = "sqlite")]
+            Error::SQLite(ref e) => e.fmt(f),
+            #[cfg(feature = "redis")]
+            Error::Redis(ref e) => e.fmt(f),
         }
     }
 }
 
 impl std::error::Error for Error {
-    fn description(&self) -> &str {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
         match *self {
-            Error::Reqwest(ref e) => e.description(),
-            Error::ParseFloat(ref e) => e.description(),
-            Error::ParseInt(ref e) => e.description(),
-            Error::MySql(ref e) => e.description(),
-            Error::MySqlMissingNamedParameter(ref e) => e.description(),
-            Error::NotFound => "No data found",
-            Error::IO(ref e) => e.description(),
-            Error::ChronoParse(ref e) => e.description(),
-            Error::CannotFetchTradesAccurately => "Cannot fetch from API",
-            Error::ParseJson(ref e) => e.description(),
-            Error::InvalidSide(_) => "Invalid side",
+            Error::Reqwest(ref e) => Some(e),
+            Error::ParseFloat(ref e) => Some(e),
+            Error::ParseInt(ref e) => Some(e),
+            Error::MySql(ref e) => Some(e.as_ref()),
+            Error::MySqlMissingNamedParameter(ref e) => Some(e.as_ref()),
+            Error::IO(ref e) => Some(e),
+            Error::ChronoParse(ref e) => Some(e),
+            Error::ParseJson(ref e) => Some(e),
+            #[cfg(feature = "parquet")]
+            Error::Arrow(ref e) => Some(e),
+            #[cfg(feature = "parquet")]
+            Error::Parquet(ref e) => Some(e),
+            #[cfg(feature = "postgres")]
+            Error::Postgres(ref e) => Some(e),
+            #[cfg(feature = "sqlite")]
+            Error::SQLite(ref e) => Some(e),
+            #[cfg(feature = "redis")]
+            Error::Redis(ref e) => Some(e),
+            _ => None,
         }
     }
 }
@@ -109,3 +387,109 @@ impl From<serde_json::error::Error> for Error {
         Error::ParseJson(err)
     }
 }
+
+#[cfg(feature = "parquet")]
+impl From<arrow::error::ArrowError> for Error {
+    fn from(err: arrow::error::ArrowError) -> Self {
+        Error::Arrow(err)
+    }
+}
+
+#[cfg(feature = "parquet")]
+impl From<parquet::errors::ParquetError> for Error {
+    fn from(err: parquet::errors::ParquetError) -> Self {
+        Error::Parquet(err)
+    }
+}
+
+#[cfg(feature = "postgres")]
+impl From<postgres::Error> for Error {
+    fn from(err: postgres::Error) -> Self {
+        Error::Postgres(err)
+    }
+}
+
+#[cfg(feature = "sqlite")]
+impl From<rusqlite::Error> for Error {
+    fn from(err: rusqlite::Error) -> Self {
+        Error::SQLite(err)
+    }
+}
+
+#[cfg(feature = "redis")]
+impl From<redis::RedisError> for Error {
+    fn from(err: redis::RedisError) -> Self {
+        Error::Redis(err)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::error::Error as _Error;
+
+    use super::*;
+
+    #[test]
+    fn error_classification_test() {
+        let rate_limited = Error::HttpStatus {
+            status: 429,
+            body: String::new(),
+        };
+        assert_eq!(rate_limited.kind(), ErrorKind::HttpStatus);
+        assert!(rate_limited.is_retryable());
+        assert!(!rate_limited.is_fatal());
+
+        let unauthorized = Error::Exchange {
+            exchange: "bitflyer".to_owned(),
+            status: 401,
+            code: Some("-500".to_owned()),
+            message: "Invalid signature".to_owned(),
+        };
+        assert_eq!(unauthorized.kind(), ErrorKind::Exchange);
+        assert!(unauthorized.is_fatal());
+        assert_eq!(unauthorized.to_string(), "bitflyer (401, -500): Invalid signature");
+
+        let parse = Error::from("x".parse::<u64>().unwrap_err());
+        assert_eq!(parse.kind(), ErrorKind::Decode);
+        assert!(parse.source().is_some());
+        assert!(!parse.is_retryable() && !parse.is_fatal());
+
+        assert!(Error::CorruptProgress(String::new()).is_fatal());
+        assert!(!Error::EmptyPage.is_retryable());
+        assert!(Error::from(std::io::Error::from(std::io::ErrorKind::TimedOut)).is_retryable());
+        assert!(!Error::from(std::io::Error::from(std::io::ErrorKind::PermissionDenied)).is_retryable());
+        #[cfg(feature = "sqlite")]
+        {
+            // SQLITE_BUSY
+            let busy = rusqlite::Error::SqliteFailure(rusqlite::ffi::Error::new(5), None);
+            assert!(Error::from(busy).is_retryable());
+            assert!(!Error::from(rusqlite::Error::QueryReturnedNoRows).is_retryable());
+        }
+        assert_eq!(Error::EmptyPage.kind(), ErrorKind::Domain);
+    }
+
+    #[test]
+    fn mysql_connection_lost_test() {
+        use mysql::error::{DriverError, Error as MySqlError, MySqlError as ServerError};
+
+        let server = |code| {
+            Error::from(MySqlError::MySqlError(ServerError {
+                state: "HY000".to_owned(),
+                message: String::new(),
+                code,
+            }))
+        };
+        assert!(Error::from(MySqlError::DriverError(DriverError::CouldNotConnect(None))).is_connection_lost());
+        assert!(Error::from(MySqlError::IoError(std::io::ErrorKind::BrokenPipe.into())).is_connection_lost());
+        assert!(server(1927).is_connection_lost());
+        // a syntax error or a duplicate key never succeeds on a new connection
+        assert!(!server(1064).is_connection_lost());
+        assert!(!server(1062).is_connection_lost());
+        assert!(!Error::EmptyPage.is_connection_lost());
+
+        assert!(server(1927).is_retryable());
+        assert!(server(1213).is_retryable());
+        assert!(!server(1213).is_connection_lost());
+        assert!(!server(1064).is_retryable());
+    }
+}
@@ -0,0 +1,136 @@
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream, ToSocketAddrs};
+use std::thread;
+use std::time::Duration;
+
+use log::{info, trace, warn};
+
+use crate::error::Result;
+
+/// A timeout of reading a request and writing a response, so a silent client cannot block others.
+const TIMEOUT: Duration = Duration::from_secs(3);
+/// The maximum size of a request line and headers.
+const MAX_REQUEST_BYTES: u64 = 16 * 1024;
+
+/// A request line of HTTP.
+#[derive(Debug)]
+pub(crate) struct Request {
+    pub method: String,
+    pub path: String,
+}
+
+/// A response of HTTP, which is sent with `Connection: close`.
+#[derive(Debug)]
+pub(crate) struct Response {
+    pub status: u16,
+    pub content_type: &'static str,
+    pub body: String,
+}
+
+impl Response {
+    pub fn new(status: u16, content_type: &'static str, body: String) -> Self {
+        Response {
+            status,
+            content_type,
+            body,
+        }
+    }
+
+    pub fn not_found() -> Self {
+        Self::new(404, "text/plain", "not found\n".to_owned())
+    }
+}
+
+fn reason(status: u16) -> &'static str {
+    match status {
+        200 => "OK",
+        400 => "Bad Request",
+        404 => "Not Found",
+        405 => "Method Not Allowed",
+        500 => "Internal Server Error",
+        503 => "Service Unavailable",
+        _ => "",
+    }
+}
+
+fn handle<H: Fn(&Request) -> Response>(stream: TcpStream, handler: &H) -> Result<()> {
+    stream.set_read_timeout(Some(TIMEOUT))?;
+    stream.set_write_timeout(Some(TIMEOUT))?;
+    let mut reader = BufReader::new(stream.try_clone()?.take(MAX_REQUEST_BYTES));
+    let mut line = String::new();
+    reader.read_line(&mut line)?;
+    // skip headers, since no handler needs them
+    let mut header = String::new();
+    while reader.read_line(&mut header)? > 2 {
+        header.clear();
+    }
+
+    let mut parts = line.split_whitespace();
+    let response = match (parts.next(), parts.next()) {
+        (Some(method), Some(target)) => {
+            let request = Request {
+                method: method.to_owned(),
+                path: target.split('?').next().unwrap_or(target).to_owned(),
+            };
+            trace!("{} {}", request.method, request.path);
+            handler(&request)
+        }
+        _ => Response::new(400, "text/plain", "bad request\n".to_owned()),
+    };
+
+    let mut stream = stream;
+    write!(
+        stream,
+        "HTTP/1.1 {} {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        response.status,
+        reason(response.status),
+        response.content_type,
+        response.body.len(),
+        response.body
+    )?;
+    stream.flush()?;
+    Ok(())
+}
+
+/// Serves HTTP on a background thread, one connection at a time.
+/// Each connection must send its request within `TIMEOUT`.
+/// Returns the bound address, which is useful with port 0.
+pub(crate) fn serve<A, H>(addr: A, handler: H) -> Result<SocketAddr>
+    where
+        A: ToSocketAddrs,
+        H: Fn(&Request) -> Response + Send + 'static,
+{
+    let listener = TcpListener::bind(addr)?;
+    let addr = listener.local_addr()?;
+    info!("listening on http://{}", addr);
+    thread::spawn(move || {
+        for stream in listener.incoming() {
+            if let Err(e) = stream.map_err(Into::into).and_then(|s| handle(s, &handler)) {
+                warn!("failed to handle a connection: {}", e);
+            }
+        }
+    });
+    Ok(addr)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Instant;
+
+    use super::*;
+
+    #[test]
+    fn silent_client_test() {
+        let addr = serve("127.0.0.1:0", |_| Response::new(200, "text/plain", "ok\n".to_owned())).unwrap();
+        // a client which connects and sends nothing
+        let _silent = TcpStream::connect(addr).unwrap();
+
+        let started = Instant::now();
+        let mut stream = TcpStream::connect(addr).unwrap();
+        write!(stream, "GET /healthz HTTP/1.1\r\nHost: localhost\r\n\r\n").unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).unwrap();
+        assert!(response.starts_with("HTTP/1.1 200 OK\r\n"));
+        assert!(started.elapsed() < TIMEOUT * 2);
+    }
+}
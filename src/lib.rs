@@ -13,12 +13,11 @@
 //!
 //! A simple downloader for Liquid with writing to stdout.
 //! This program creates `/tmp/qn-progress.txt` for recording progress,
-//! so delete it if you want to run again from the starting point.
+//! so delete it (and `/tmp/qn-progress.txt.bak`) if you want to run again from the starting point.
 //!
 //! ```
 //! use std::path::PathBuf;
 //! use std::thread;
-//! use std::time::Duration;
 //!
 //! use chrono::offset::TimeZone;
 //! use chrono::Utc;
@@ -31,38 +30,28 @@
 //! fn main() {
 //!     // by using thread, you can run multiple downloaders
 //!     let liquid = thread::spawn(move || {
-//!         while {
-//!             // download data from 2019-01-01T01:01:01Z to 2019-01-01T01:03:01Z
-//!             // output the downloaded data to standard out (println!)
-//!             let downloader = LiquidDownloader::new(
-//!                 Utc.ymd(2019, 1, 1).and_hms(1, 1, 1),
-//!                 Utc.ymd(2019, 1, 1).and_hms(1, 3, 1),
-//!             );
+//!         // download data from 2019-01-01T01:01:01Z to 2019-01-01T01:03:01Z
+//!         // output the downloaded data to standard out (println!)
+//!         let downloader = LiquidDownloader::new(
+//!             Utc.ymd(2019, 1, 1).and_hms(1, 1, 1),
+//!             Utc.ymd(2019, 1, 1).and_hms(1, 3, 1),
+//!         );
 //!
-//!             // Locate progress file to `/tmp/qn-progress.txt`.
-//!             // You can control the starting point of downloading
-//!             // by preparing this file before you run.
-//!             let mut recorder = FileRecorder::new(PathBuf::from("/tmp/qn-progress.txt"));
+//!         // Locate progress file to `/tmp/qn-progress.txt`.
+//!         // You can control the starting point of downloading
+//!         // by preparing this file before you run.
+//!         let mut recorder = FileRecorder::new(PathBuf::from("/tmp/qn-progress.txt"));
 //!
-//!             // write out to standard out. simple writer for debugging
-//!             let mut writer = StdOutWriter::default();
+//!         // write out to standard out. simple writer for debugging
+//!         let mut writer = StdOutWriter::default();
 //!
-//!             println!("start QnDownloader");
+//!         println!("start QnDownloader");
 //!
-//!             // run!
-//!             match downloader.run(&mut writer, &mut recorder) {
-//!                 Ok(_) => {
-//!                     println!("finished");
-//!                     false
-//!                 }
-//!                 Err(e) => {
-//!                     println!("error: {}", e);
-//!                     println!("retry...");
-//!                     thread::sleep(Duration::from_secs(5));
-//!                     true
-//!                 }
-//!             }
-//!         } {}
+//!         // run! see `examples/main.rs` to retry after errors
+//!         match downloader.run(&mut writer, &mut recorder) {
+//!             Ok(report) => println!("finished: {:?}", report.stop_reason),
+//!             Err(e) => println!("error: {}", e),
+//!         }
 //!     });
 //!
 //!     match liquid
@@ -77,8 +66,20 @@
 pub use self::downloader::bf::BfDownloader;
 pub use self::downloader::bitmex::MexDownloader;
 pub use self::downloader::liquid::LiquidDownloader;
+pub use self::recorder::db_mysql::MySQLRecorder;
+#[cfg(feature = "redis")]
+pub use self::recorder::db_redis::RedisRecorder;
 pub use self::recorder::file::FileRecorder;
+pub use self::recorder::store::ProgressStore;
+pub use self::writer::db_clickhouse::ClickHouseWriter;
 pub use self::writer::db_mysql::MySQLWriter;
+#[cfg(feature = "postgres")]
+pub use self::writer::db_postgres::PostgresWriter;
+#[cfg(feature = "sqlite")]
+pub use self::writer::db_sqlite::SQLiteWriter;
+pub use self::writer::jsonl::JsonLinesWriter;
+#[cfg(feature = "parquet")]
+pub use self::writer::parquet::ParquetWriter;
 pub use self::writer::stdout::StdOutWriter;
 
 /// Clients for exchange APIs.
@@ -87,10 +88,24 @@ mod api;
 pub mod downloader;
 /// Error utilities.
 pub mod error;
+/// A minimal HTTP server for local endpoints.
+#[cfg(any(feature = "metrics", feature = "status"))]
+mod http;
+/// Metrics of downloaders in the Prometheus text format.
+#[cfg(feature = "metrics")]
+pub mod metrics;
 /// Writers to output the trade data into external storage.
 pub mod writer;
 /// Recorders to record the progress on external storage to resume.
 pub mod recorder;
+/// States of jobs with health and control endpoints.
+#[cfg(feature = "status")]
+pub mod status;
+/// Mocks and fault injection for tests.
+#[cfg(any(test, feature = "testing"))]
+pub mod testing;
+/// Transports to send requests to exchanges, including cassettes to record and replay them.
+pub mod transport;
 
 #[cfg(test)]
 mod tests {
@@ -98,13 +113,12 @@ mod tests {
     use chrono::Utc;
 
     use crate::downloader::Downloader;
-    use crate::downloader::mock::RawData;
+    use crate::downloader::report::{RunReport, StopReason};
     use crate::recorder::memory::MemoryRecorder;
     use crate::recorder::ProgressRecorder;
+    use crate::testing::{MockDownloader, MockTransactionalWriter, MockWriter, RawData};
     use crate::writer::Trade;
 
-    use super::*;
-
     #[test]
     fn downloader_test() {
         let data = vec![
@@ -117,9 +131,9 @@ mod tests {
             RawData { id: 19 },
         ];
 
-        let downloader = downloader::mock::MockDownloader::new(data, 10, 16);
+        let downloader = MockDownloader::new(data, 10, 16);
 
-        let mut writer = writer::mock::MockWriter::new();
+        let mut writer = MockWriter::new();
         let mut progress_recorder = MemoryRecorder::default();
 
         let actual = downloader
@@ -148,24 +162,21 @@ mod tests {
                     quantity: 1.5,
                     price: 0.15,
                 },
-                // Notice: the downloading will overrun
-                Trade {
-                    id: String::from("17"),
-                    traded_at: Utc.ymd(1970, 1, 1).and_hms(0, 0, 17),
-                    quantity: 1.7,
-                    price: 0.17,
-                },
+                // 17 is fetched in the last page, but it's out of the range
             ],
         );
 
         let rec = progress_recorder.read();
         assert_eq!(rec.is_ok(), true);
+        let rec: serde_json::Value = serde_json::from_str(&rec.unwrap()).unwrap();
         // 17 + 1
-        assert_eq!(rec.unwrap(), r#"{"current":18}"#);
+        assert_eq!(rec["id"], serde_json::json!({"current": 18}));
+        assert_eq!(rec["version"], 1);
+        assert_eq!(rec["range"], serde_json::json!(["10", "16"]));
 
         // run again
         {
-            let mut writer = writer::mock::MockWriter::new();
+            let mut writer = MockWriter::new();
             let actual2 = downloader
                 .run(&mut writer, &mut progress_recorder)
                 .map(|_| writer.store);
@@ -177,4 +188,54 @@ mod tests {
             );
         }
     }
+
+    #[test]
+    fn caught_up_test() {
+        let data = vec![RawData { id: 10 }, RawData { id: 11 }, RawData { id: 15 }];
+        let downloader = MockDownloader::new(data, 10, 100);
+        let mut writer = MockWriter::new();
+        let mut progress_recorder = MemoryRecorder::default();
+
+        // the end is beyond the latest trade, so an empty page is not an error
+        let mut observed = vec![];
+        let report = downloader
+            .run_with_observer(&mut writer, &mut progress_recorder, |r: &RunReport| {
+                observed.push((r.batches, r.written, r.final_id.clone()))
+            })
+            .unwrap();
+        assert_eq!(report.stop_reason, StopReason::CaughtUp);
+        assert_eq!(report.batches, 2);
+        assert_eq!((report.fetched, report.written, report.deduplicated), (3, 3, 0));
+        assert_eq!(report.first_traded_at, Some(Utc.timestamp(10, 0)));
+        assert_eq!(report.last_traded_at, Some(Utc.timestamp(15, 0)));
+        assert_eq!(report.final_id, "16");
+        assert_eq!(writer.store.len(), 3);
+        assert_eq!(observed, vec![(1, 2, "12".to_owned()), (2, 3, "16".to_owned())]);
+
+        let data = vec![RawData { id: 10 }, RawData { id: 11 }];
+        let downloader = MockDownloader::new(data, 10, 11);
+        let report = downloader.run(&mut writer, &mut MemoryRecorder::default()).unwrap();
+        assert_eq!(report.stop_reason, StopReason::RangeExhausted);
+    }
+
+    #[test]
+    fn transactional_downloader_test() {
+        let data = vec![
+            RawData { id: 4 },
+            RawData { id: 10 },
+            RawData { id: 11 },
+            RawData { id: 15 },
+        ];
+
+        let downloader = MockDownloader::new(data, 10, 12);
+        let mut writer = MockTransactionalWriter::default();
+
+        assert_eq!(downloader.run_transactional(&mut writer).is_ok(), true);
+        assert_eq!(
+            writer.store.iter().map(|t| t.id.as_str()).collect::<Vec<&str>>(),
+            vec!["10", "11"],
+        );
+        let progress: serde_json::Value = serde_json::from_str(&writer.progress).unwrap();
+        assert_eq!(progress["id"], serde_json::json!({"current": 12}));
+    }
 }
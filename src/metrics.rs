@@ -0,0 +1,312 @@
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::net::{SocketAddr, ToSocketAddrs};
+use std::sync::{Arc, Mutex, MutexGuard};
+
+use chrono::{DateTime, NaiveDateTime, TimeZone, Utc};
+
+use crate::downloader::report::{RunObserver, RunReport, RunState};
+use crate::error::Result;
+use crate::http::{self, Response};
+
+/// Numbers of a job, accumulated over runs.
+#[derive(Debug, Default)]
+struct JobMetrics {
+    requests: u64,
+    bytes: u64,
+    statuses: BTreeMap<u16, u64>,
+    batches: u64,
+    written: u64,
+    retries: u64,
+    newest_traded_at: Option<DateTime<Utc>>,
+    last_batch_at: Option<DateTime<Utc>>,
+    current_id: Option<f64>,
+}
+
+/// Metrics of downloaders, exported in the Prometheus text format.
+///
+/// Each job is fed by a `MetricsObserver` passed to `Downloader::run_with_observer`.
+/// Counters are accumulated over runs, so a job can be retried with a new observer.
+///
+/// # Example
+///
+/// ```no_run
+/// use pikmin::metrics::Metrics;
+///
+/// let metrics = Metrics::new();
+/// metrics.serve("127.0.0.1:9898").unwrap();
+/// let observer = metrics.observer("bf");
+/// // downloader.run_with_observer(&mut writer, &mut recorder, observer)
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct Metrics {
+    jobs: Arc<Mutex<BTreeMap<String, JobMetrics>>>,
+}
+
+impl Metrics {
+    /// Creates empty metrics.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn lock(&self) -> MutexGuard<'_, BTreeMap<String, JobMetrics>> {
+        self.jobs.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Creates an observer which feeds metrics of a job.
+    pub fn observer(&self, job: &str) -> MetricsObserver {
+        self.lock().entry(job.to_owned()).or_default();
+        MetricsObserver {
+            metrics: self.clone(),
+            job: job.to_owned(),
+            last: RunReport::new(),
+        }
+    }
+
+    /// Renders all metrics in the Prometheus text format.
+    pub fn render(&self) -> String {
+        let jobs = self.lock();
+        let now = Utc::now();
+        let mut out = String::new();
+
+        let mut family = |name: &str, kind: &str, help: &str, samples: Vec<(String, String)>| {
+            if samples.is_empty() {
+                return;
+            }
+            let _ = writeln!(out, "# HELP {} {}", name, help);
+            let _ = writeln!(out, "# TYPE {} {}", name, kind);
+            for (labels, value) in samples {
+                let _ = writeln!(out, "{}{{{}}} {}", name, labels, value);
+            }
+        };
+        let each = |f: &dyn Fn(&str, &JobMetrics) -> Option<String>| {
+            jobs.iter()
+                .filter_map(|(job, m)| f(job, m).map(|v| (format!("job=\"{}\"", escape(job)), v)))
+                .collect::<Vec<_>>()
+        };
+
+        family(
+            "pikmin_requests_total",
+            "counter",
+            "Requests to the exchange.",
+            each(&|_, m| Some(m.requests.to_string())),
+        );
+        family(
+            "pikmin_responses_total",
+            "counter",
+            "Responses from the exchange by HTTP status codes.",
+            jobs.iter()
+                .flat_map(|(job, m)| {
+                    m.statuses.iter().map(move |(status, n)| {
+                        (format!("job=\"{}\",status=\"{}\"", escape(job), status), n.to_string())
+                    })
+                })
+                .collect(),
+        );
+        family(
+            "pikmin_response_bytes_total",
+            "counter",
+            "Bytes of response bodies.",
+            each(&|_, m| Some(m.bytes.to_string())),
+        );
+        family(
+            "pikmin_batches_total",
+            "counter",
+            "Batches written and recorded.",
+            each(&|_, m| Some(m.batches.to_string())),
+        );
+        family(
+            "pikmin_trades_written_total",
+            "counter",
+            "Trades written, as reported by writers.",
+            each(&|_, m| Some(m.written.to_string())),
+        );
+        family(
+            "pikmin_retries_total",
+            "counter",
+            "Batches retried after transient errors.",
+            each(&|_, m| Some(m.retries.to_string())),
+        );
+        family(
+            "pikmin_lag_seconds",
+            "gauge",
+            "Seconds between the newest written trade and now.",
+            each(&|_, m| m.newest_traded_at.map(|t| seconds(now - t))),
+        );
+        family(
+            "pikmin_last_batch_timestamp_seconds",
+            "gauge",
+            "Unix time of the last successful batch.",
+            each(&|_, m| m.last_batch_at.map(|t| format!("{}", t.timestamp_millis() as f64 / 1000.0))),
+        );
+        family(
+            "pikmin_current_id",
+            "gauge",
+            "The current ID of each job, if it is a number, or a time in Unix seconds.",
+            each(&|_, m| m.current_id.map(|id| id.to_string())),
+        );
+        out
+    }
+
+    /// Serves `/metrics` over HTTP on a background thread, e.g. at `127.0.0.1:9898`.
+    /// Returns the bound address.
+    pub fn serve(&self, addr: impl ToSocketAddrs) -> Result<SocketAddr> {
+        let metrics = self.clone();
+        http::serve(addr, move |req| match (req.method.as_str(), req.path.as_str()) {
+            ("GET", "/metrics") => Response::new(200, "text/plain; version=0.0.4", metrics.render()),
+            _ => Response::not_found(),
+        })
+    }
+}
+
+fn seconds(d: chrono::Duration) -> String {
+    format!("{}", d.num_milliseconds() as f64 / 1000.0)
+}
+
+/// Returns a value of an ID, which is a number, or a time of a date-time ID (e.g. `LiquidDownloader`).
+fn id_value(id: &str) -> Option<f64> {
+    id.parse().ok().or_else(|| {
+        NaiveDateTime::parse_from_str(id, "%Y-%m-%d %H:%M:%S%.f UTC")
+            .ok()
+            .map(|t| Utc.from_utc_datetime(&t).timestamp_millis() as f64 / 1000.0)
+    })
+}
+
+/// Escapes a label value.
+fn escape(s: &str) -> String {
+    s.replace('\\', r"\\").replace('"', "\\\"").replace('\n', r"\n")
+}
+
+/// An observer which feeds `Metrics` of a job. See `Metrics::observer`.
+#[derive(Debug)]
+pub struct MetricsObserver {
+    metrics: Metrics,
+    job: String,
+    /// The previous report, to add only the increase.
+    last: RunReport,
+}
+
+impl MetricsObserver {
+    /// Adds the increase of counters since the previous report.
+    fn add_counters(&mut self, report: &RunReport) {
+        let last = &self.last;
+        let mut jobs = self.metrics.lock();
+        let m = jobs.entry(self.job.clone()).or_default();
+
+        m.requests += report.requests.saturating_sub(last.requests);
+        m.bytes += report.bytes.saturating_sub(last.bytes);
+        for (status, n) in &report.statuses {
+            let before = last.statuses.get(status).cloned().unwrap_or(0);
+            *m.statuses.entry(*status).or_insert(0) += n.saturating_sub(before);
+        }
+        m.batches += report.batches.saturating_sub(last.batches);
+        m.written += report.written.saturating_sub(last.written);
+        m.retries += report.retries.saturating_sub(last.retries);
+        drop(jobs);
+
+        self.last = report.clone();
+    }
+}
+
+impl RunObserver for MetricsObserver {
+    fn on_batch(&mut self, report: &RunReport) {
+        if report.batches <= self.last.batches {
+            // a new run with the same observer
+            self.last = RunReport::new();
+        }
+        self.add_counters(report);
+
+        let mut jobs = self.metrics.lock();
+        let m = jobs.entry(self.job.clone()).or_default();
+        if report.last_traded_at > m.newest_traded_at {
+            m.newest_traded_at = report.last_traded_at;
+        }
+        m.last_batch_at = Some(Utc::now());
+        if let Some(id) = id_value(&report.final_id) {
+            m.current_id = Some(id);
+        }
+    }
+
+    fn on_failed_batch(&mut self, report: &RunReport) {
+        // requests and retries of failed batches count, even if the run returns the error
+        self.add_counters(report);
+    }
+
+    fn on_state(&mut self, state: RunState) {
+        if state == RunState::Finished || state == RunState::Failed {
+            self.last = RunReport::new();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{Read, Write};
+    use std::net::TcpStream;
+
+    use crate::downloader::Downloader;
+    use crate::recorder::memory::MemoryRecorder;
+    use crate::testing::{FaultyDownloader, MockDownloader, MockWriter, RawData};
+
+    use super::*;
+
+    fn get(addr: SocketAddr, path: &str) -> String {
+        let mut stream = TcpStream::connect(addr).unwrap();
+        write!(stream, "GET {} HTTP/1.1\r\nHost: localhost\r\n\r\n", path).unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).unwrap();
+        response
+    }
+
+    #[test]
+    fn metrics_test() {
+        let metrics = Metrics::new();
+        let mut observer = metrics.observer("bf");
+        let mut report = RunReport::new();
+        report.batches = 1;
+        report.requests = 2;
+        report.statuses.insert(200, 1);
+        report.statuses.insert(429, 1);
+        report.written = 500;
+        report.last_traded_at = Some(Utc.timestamp(1_546_300_800, 0));
+        report.final_id = "12345".to_owned();
+        observer.on_batch(&report);
+
+        // a new run adds to the counters
+        report.statuses.remove(&429);
+        report.requests = 1;
+        report.final_id = "12346".to_owned();
+        metrics.observer("bf").on_batch(&report);
+
+        let text = metrics.render();
+        assert!(text.contains("# TYPE pikmin_requests_total counter\npikmin_requests_total{job=\"bf\"} 3\n"));
+        assert!(text.contains("pikmin_responses_total{job=\"bf\",status=\"200\"} 2\n"));
+        assert!(text.contains("pikmin_responses_total{job=\"bf\",status=\"429\"} 1\n"));
+        assert!(text.contains("pikmin_trades_written_total{job=\"bf\"} 1000\n"));
+        assert!(text.contains("pikmin_current_id{job=\"bf\"} 12346\n"));
+        assert!(text.contains("pikmin_lag_seconds{job=\"bf\"} "));
+
+        let addr = metrics.serve("127.0.0.1:0").unwrap();
+        let response = get(addr, "/metrics");
+        assert!(response.starts_with("HTTP/1.1 200 OK\r\n"));
+        assert!(response.contains("pikmin_batches_total{job=\"bf\"} 2\n"));
+        assert!(get(addr, "/").starts_with("HTTP/1.1 404 Not Found\r\n"));
+    }
+
+    #[test]
+    fn metrics_on_error_test() {
+        let metrics = Metrics::new();
+        let source = RawData::from_ids(&[10, 11]);
+        let downloader = FaultyDownloader::new(MockDownloader::new(source, 10, 12).with_retries(1)).fail_on(&[1, 2]);
+        let result = downloader.run_with_observer(
+            &mut MockWriter::new(),
+            &mut MemoryRecorder::default(),
+            metrics.observer("bf"),
+        );
+        assert!(result.is_err());
+        assert!(metrics.render().contains("pikmin_retries_total{job=\"bf\"} 1\n"));
+
+        assert_eq!(id_value("2019-01-01 00:00:01.5 UTC"), Some(1_546_300_801.5));
+        assert_eq!(id_value(r#"{"id":1}"#), None);
+    }
+}
@@ -0,0 +1,100 @@
+use std::cell::Cell;
+
+use chrono::Utc;
+use log::trace;
+use mysql::{params, Params, Pool};
+
+use crate::error::Error;
+use crate::error::Result;
+use crate::recorder::ProgressRecorder;
+
+/// A progress recorder on a MySQL table, keyed by a job name.
+///
+/// This can share a connection pool with `MySQLWriter` (see `MySQLWriter::pool`),
+/// and `MySQLTransactionalWriter` commits trades and progress in one transaction.
+#[derive(Debug)]
+pub struct MySQLRecorder {
+    table_name: String,
+    job: String,
+    table_created: Cell<bool>,
+    connection: Pool,
+}
+
+impl MySQLRecorder {
+    /// Creates a recorder with a connection pool, a table name and a job name.
+    pub fn new(connection: Pool, table_name: &str, job: &str) -> Self {
+        MySQLRecorder {
+            table_name: table_name.to_owned(),
+            job: job.to_owned(),
+            table_created: Cell::new(false),
+            connection,
+        }
+    }
+
+    pub(crate) fn create_table_stmt(&self) -> String {
+        format!(
+            r"CREATE TABLE IF NOT EXISTS {} ( job VARCHAR(64) NOT NULL PRIMARY KEY, progress TEXT NOT NULL, updated_at TIMESTAMP(3) NOT NULL );",
+            self.table_name
+        )
+    }
+
+    pub(crate) fn create_table(&self) -> Result<()> {
+        if !self.table_created.get() {
+            self.connection.prep_exec(self.create_table_stmt(), ())?;
+            self.table_created.set(true);
+        }
+        Ok(())
+    }
+
+    /// Replaces the connection pool, e.g. after a writer sharing it has reconnected.
+    pub(crate) fn set_pool(&mut self, connection: Pool) {
+        self.connection = connection;
+    }
+
+    /// Forgets the table has been created, e.g. after reconnecting to a database which might have lost it.
+    pub(crate) fn reset(&self) {
+        self.table_created.set(false);
+    }
+
+    /// Returns a statement and its params to record progress.
+    pub(crate) fn out_stmt(&self, json: &str) -> (String, Params) {
+        let stmt = format!(
+            r"REPLACE INTO {} (job, progress, updated_at) VALUES (:job, :progress, :updated_at);",
+            self.table_name
+        );
+        let p = params! {
+            "job" => self.job.clone(),
+            "progress" => json.to_owned(),
+            "updated_at" => Utc::now().naive_utc(),
+        };
+        (stmt, p.into())
+    }
+}
+
+impl ProgressRecorder for MySQLRecorder {
+    fn read(&self) -> Result<String> {
+        self.create_table()?;
+        let stmt = format!(
+            r"SELECT progress FROM {} WHERE job = :job;",
+            self.table_name
+        );
+        let mut result = self
+            .connection
+            .prep_exec(stmt, params! { "job" => self.job.clone() })?;
+        let progress = match result.next() {
+            Some(row) => row?.get::<String, usize>(0).unwrap_or_default(),
+            None => String::new(),
+        };
+        trace!("read: {}", progress);
+        Ok(progress)
+    }
+
+    fn out(&mut self, json: &str) -> Result<()> {
+        self.create_table()?;
+        let (stmt, p) = self.out_stmt(json);
+        self.connection
+            .prep_exec(stmt, p)
+            .map(|_| ())
+            .map_err(Error::from)
+    }
+}
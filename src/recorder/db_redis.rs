@@ -0,0 +1,438 @@
+use std::cell::RefCell;
+use std::cmp;
+use std::fmt;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::mpsc::{self, RecvTimeoutError, Sender};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use log::{info, trace, warn};
+use redis::{Client, Connection};
+
+use crate::error::Error;
+use crate::error::Result;
+use crate::recorder::ProgressRecorder;
+
+/// Extends the lease, if it is held by `ARGV[1]`.
+const RENEW_SCRIPT: &str = r"if redis.call('GET', KEYS[1]) == ARGV[1] then return redis.call('PEXPIRE', KEYS[1], ARGV[2]) else return 0 end";
+/// Writes progress and extends the lease, if it is held by `ARGV[1]`.
+const WRITE_SCRIPT: &str = r"if redis.call('GET', KEYS[1]) == ARGV[1] then redis.call('PEXPIRE', KEYS[1], ARGV[2]); redis.call('SET', KEYS[2], ARGV[3]); return 1 else return 0 end";
+/// Deletes the lease, if it is held by `ARGV[1]`.
+const RELEASE_SCRIPT: &str = r"if redis.call('GET', KEYS[1]) == ARGV[1] then return redis.call('DEL', KEYS[1]) else return 0 end";
+
+/// Returns a token which identifies an instance holding a lease.
+fn new_token() -> String {
+    static COUNTER: AtomicUsize = AtomicUsize::new(0);
+    let nanos = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_nanos())
+        .unwrap_or_default();
+    format!(
+        "{}-{}-{}",
+        std::process::id(),
+        nanos,
+        COUNTER.fetch_add(1, Ordering::SeqCst)
+    )
+}
+
+/// Runs a script with keys and arguments.
+fn eval(connection: &mut Connection, script: &str, keys: &[&str], args: &[String]) -> Result<i64> {
+    let mut cmd = redis::cmd("EVAL");
+    cmd.arg(script).arg(keys.len());
+    for k in keys {
+        cmd.arg(*k);
+    }
+    for a in args {
+        cmd.arg(a.as_str());
+    }
+    Ok(cmd.query(connection)?)
+}
+
+/// A thread which extends a lease at a third of its duration, until it is stopped or the lease is lost.
+struct Renewal {
+    stop: Sender<()>,
+    handle: JoinHandle<()>,
+}
+
+impl Renewal {
+    fn start(mut connection: Connection, job: String, lock_key: String, token: String, lease: Duration) -> Self {
+        let (stop, stopped) = mpsc::channel();
+        let handle = thread::spawn(move || {
+            let args = [token, (lease.as_millis() as u64).to_string()];
+            while let Err(RecvTimeoutError::Timeout) = stopped.recv_timeout(lease / 3) {
+                match eval(&mut connection, RENEW_SCRIPT, &[&lock_key], &args) {
+                    Ok(1) => trace!("renewed the lease of {}", job),
+                    Ok(_) => {
+                        warn!("lost the lease of {}", job);
+                        break;
+                    }
+                    // the next renewal may succeed before the lease expires
+                    Err(e) => warn!("cannot renew the lease of {}: {}", job, e),
+                }
+            }
+        });
+        Renewal { stop, handle }
+    }
+
+    fn stop(self) {
+        drop(self.stop);
+        if self.handle.join().is_err() {
+            warn!("the renewal thread panicked");
+        }
+    }
+}
+
+/// A progress recorder on Redis, with a lease lock to run only one instance of a job.
+///
+/// Progress is stored in `pikmin:<job>:progress`, and the lock in `pikmin:<job>:lock`
+/// with `SET NX PX`. While the lease is held, a background thread extends it on another connection,
+/// so it doesn't expire during long sleeps between writes (e.g. `follow_interval`) as long as the process is alive.
+/// Each `out` also extends the lease, and fails with `Error::Locked` if the lease has been taken
+/// by another instance. The lease is released when the recorder is dropped.
+///
+/// # Example
+///
+/// ```no_run
+/// use std::time::Duration;
+/// use pikmin::recorder::db_redis::RedisRecorder;
+///
+/// let mut recorder = RedisRecorder::new("redis://127.0.0.1/", "bf").unwrap();
+/// // wait while another instance is running
+/// recorder.acquire(Duration::from_secs(600)).unwrap();
+/// // downloader.run(&mut writer, &mut recorder)
+/// ```
+pub struct RedisRecorder {
+    url: String,
+    job: String,
+    progress_key: String,
+    lock_key: String,
+    token: String,
+    lease: Duration,
+    held: bool,
+    renews: bool,
+    renewal: Option<Renewal>,
+    connection: RefCell<Connection>,
+}
+
+impl fmt::Debug for RedisRecorder {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        // `redis::Connection` doesn't implement `Debug`
+        f.debug_struct("RedisRecorder")
+            .field("job", &self.job)
+            .field("token", &self.token)
+            .field("lease", &self.lease)
+            .field("held", &self.held)
+            .field("renews", &self.renews)
+            .finish()
+    }
+}
+
+impl RedisRecorder {
+    /// Creates a recorder with a URL (e.g. `redis://127.0.0.1/`) and a job name.
+    /// The lease is not acquired yet.
+    pub fn new(url: &str, job: &str) -> Result<Self> {
+        trace!("connect to {}", url);
+        let connection = Client::open(url)?.get_connection()?;
+
+        Ok(RedisRecorder {
+            url: url.to_owned(),
+            job: job.to_owned(),
+            progress_key: format!("pikmin:{}:progress", job),
+            lock_key: format!("pikmin:{}:lock", job),
+            token: new_token(),
+            lease: Duration::from_secs(30),
+            held: false,
+            renews: true,
+            renewal: None,
+            connection: RefCell::new(connection),
+        })
+    }
+
+    /// Sets a duration of the lease. The default is 30 seconds.
+    pub fn with_lease(mut self, lease: Duration) -> Self {
+        self.lease = lease;
+        self
+    }
+
+    /// Sets whether to extend the lease in a background thread. The default is true.
+    /// Without it, the lease is extended only by `out` and `renew`, so it must be longer than an interval of writes.
+    pub fn with_renewal(mut self, renews: bool) -> Self {
+        self.renews = renews;
+        self
+    }
+
+    fn lease_millis(&self) -> u64 {
+        self.lease.as_millis() as u64
+    }
+
+    fn eval(&self, script: &str, keys: &[&str], args: &[String]) -> Result<i64> {
+        eval(&mut self.connection.borrow_mut(), script, keys, args)
+    }
+
+    fn start_renewal(&mut self) -> Result<()> {
+        self.stop_renewal();
+        let connection = Client::open(self.url.as_str())?.get_connection()?;
+        self.renewal = Some(Renewal::start(
+            connection,
+            self.job.clone(),
+            self.lock_key.clone(),
+            self.token.clone(),
+            self.lease,
+        ));
+        Ok(())
+    }
+
+    fn stop_renewal(&mut self) {
+        if let Some(renewal) = self.renewal.take() {
+            renewal.stop();
+        }
+    }
+
+    /// Marks the lease as taken by another instance.
+    fn lost(&mut self) {
+        self.stop_renewal();
+        self.held = false;
+    }
+
+    /// Tries to acquire the lease once. Returns false if another instance holds it.
+    pub fn try_acquire(&mut self) -> Result<bool> {
+        if self.held {
+            return Ok(true);
+        }
+        let result: Option<String> = redis::cmd("SET")
+            .arg(&self.lock_key)
+            .arg(&self.token)
+            .arg("NX")
+            .arg("PX")
+            .arg(self.lease_millis())
+            .query(&mut *self.connection.borrow_mut())?;
+        self.held = result.is_some();
+        if self.held {
+            info!("acquired the lease of {}", self.job);
+            if self.renews {
+                self.start_renewal()?;
+            }
+        }
+        Ok(self.held)
+    }
+
+    /// Acquires the lease, waiting for another instance to release it or to expire.
+    /// Returns `Error::Locked` if it cannot be acquired within `timeout`.
+    /// Use `Duration::from_secs(0)` to exit immediately.
+    pub fn acquire(&mut self, timeout: Duration) -> Result<()> {
+        let started = Instant::now();
+        loop {
+            if self.try_acquire()? {
+                return Ok(());
+            }
+            let elapsed = started.elapsed();
+            if elapsed >= timeout {
+                return Err(Error::Locked(self.job.clone()));
+            }
+            trace!("{} is locked by another instance", self.job);
+            thread::sleep(cmp::min(self.lease / 4, timeout - elapsed));
+        }
+    }
+
+    /// Extends the lease. Returns `Error::Locked` if it is not held anymore.
+    pub fn renew(&mut self) -> Result<()> {
+        let args = [self.token.clone(), self.lease_millis().to_string()];
+        if self.eval(RENEW_SCRIPT, &[&self.lock_key], &args)? == 1 {
+            Ok(())
+        } else {
+            self.lost();
+            Err(Error::Locked(self.job.clone()))
+        }
+    }
+
+    /// Releases the lease, if it is held.
+    pub fn release(&mut self) -> Result<()> {
+        self.stop_renewal();
+        if self.held {
+            self.eval(RELEASE_SCRIPT, &[&self.lock_key], std::slice::from_ref(&self.token))?;
+            self.held = false;
+            info!("released the lease of {}", self.job);
+        }
+        Ok(())
+    }
+}
+
+impl Drop for RedisRecorder {
+    fn drop(&mut self) {
+        if let Err(e) = self.release() {
+            warn!("cannot release the lease of {}: {}", self.job, e);
+        }
+    }
+}
+
+impl ProgressRecorder for RedisRecorder {
+    fn read(&self) -> Result<String> {
+        let progress: Option<String> = redis::cmd("GET")
+            .arg(&self.progress_key)
+            .query(&mut *self.connection.borrow_mut())?;
+        trace!("read: {:?}", progress);
+        progress.ok_or(Error::NoProgress)
+    }
+
+    fn out(&mut self, json: &str) -> Result<()> {
+        let args = [
+            self.token.clone(),
+            self.lease_millis().to_string(),
+            json.to_owned(),
+        ];
+        let keys = [self.lock_key.as_str(), self.progress_key.as_str()];
+        if self.eval(WRITE_SCRIPT, &keys, &args)? == 1 {
+            Ok(())
+        } else {
+            self.lost();
+            Err(Error::Locked(self.job.clone()))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use std::io::{BufRead, BufReader, Write};
+    use std::net::{TcpListener, TcpStream};
+    use std::sync::{Arc, Mutex};
+
+    use super::*;
+
+    type Store = Arc<Mutex<HashMap<String, (String, Option<Instant>)>>>;
+
+    fn read_command(reader: &mut BufReader<TcpStream>) -> Option<Vec<String>> {
+        let mut line = String::new();
+        if reader.read_line(&mut line).ok()? == 0 {
+            return None;
+        }
+        let n: usize = line.trim()[1..].parse().ok()?;
+        (0..n)
+            .map(|_| {
+                let mut len = String::new();
+                reader.read_line(&mut len).ok()?;
+                let mut data = String::new();
+                reader.read_line(&mut data).ok()?;
+                Some(data.trim_end_matches("\r\n").to_owned())
+            })
+            .collect()
+    }
+
+    /// Answers a command, emulating the scripts of the recorder instead of running Lua.
+    fn answer(store: &Store, cmd: &[String]) -> String {
+        let mut store = store.lock().unwrap();
+        let now = Instant::now();
+        store.retain(|_, (_, expiry)| expiry.is_none_or(|e| e > now));
+        let px = |ms: &str| Some(now + Duration::from_millis(ms.parse().unwrap()));
+        let bulk = |v: Option<&String>| match v {
+            Some(s) => format!("${}\r\n{}\r\n", s.len(), s),
+            None => "$-1\r\n".to_owned(),
+        };
+
+        match cmd[0].as_str() {
+            "GET" => bulk(store.get(&cmd[1]).map(|v| &v.0)),
+            "SET" if store.contains_key(&cmd[1]) => bulk(None),
+            "SET" => {
+                store.insert(cmd[1].clone(), (cmd[2].clone(), px(&cmd[5])));
+                "+OK\r\n".to_owned()
+            }
+            "EVAL" => {
+                let n: usize = cmd[2].parse().unwrap();
+                let (keys, args) = cmd[3..].split_at(n);
+                if store.get(&keys[0]).is_none_or(|v| v.0 != args[0]) {
+                    return ":0\r\n".to_owned();
+                }
+                if cmd[1] == RELEASE_SCRIPT {
+                    store.remove(&keys[0]);
+                } else {
+                    store.get_mut(&keys[0]).unwrap().1 = px(&args[1]);
+                }
+                if cmd[1] == WRITE_SCRIPT {
+                    store.insert(keys[1].clone(), (args[2].clone(), None));
+                }
+                ":1\r\n".to_owned()
+            }
+            c => format!("-ERR unknown command {}\r\n", c),
+        }
+    }
+
+    /// Starts a Redis-protocol stand-in, and returns its URL.
+    fn serve() -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("redis://{}/", listener.local_addr().unwrap());
+        let store = Store::default();
+        thread::spawn(move || {
+            for stream in listener.incoming() {
+                let store = store.clone();
+                let mut stream = stream.unwrap();
+                thread::spawn(move || {
+                    let mut reader = BufReader::new(stream.try_clone().unwrap());
+                    while let Some(cmd) = read_command(&mut reader) {
+                        stream.write_all(answer(&store, &cmd).as_bytes()).unwrap();
+                    }
+                });
+            }
+        });
+        url
+    }
+
+    #[test]
+    fn redis_lease_test() {
+        let url = serve();
+        let lease = Duration::from_millis(300);
+
+        let mut first = RedisRecorder::new(&url, "bf")
+            .unwrap()
+            .with_lease(lease)
+            .with_renewal(false);
+        let mut second = RedisRecorder::new(&url, "bf").unwrap().with_lease(lease);
+        match first.read() {
+            Err(Error::NoProgress) => (),
+            r => panic!("unexpected: {:?}", r),
+        }
+
+        first.acquire(Duration::from_secs(0)).unwrap();
+        first.out(r#"{"current":3}"#).unwrap();
+        assert!(!second.try_acquire().unwrap());
+        assert_eq!(second.read().unwrap(), r#"{"current":3}"#);
+
+        // the lease expires without renewal, and the second instance takes over
+        second.acquire(Duration::from_secs(2)).unwrap();
+        match first.out(r#"{"current":4}"#) {
+            Err(Error::Locked(_)) => (),
+            r => panic!("unexpected: {:?}", r),
+        }
+        second.out(r#"{"current":5}"#).unwrap();
+        second.renew().unwrap();
+
+        drop(second);
+        assert!(first.try_acquire().unwrap());
+        assert_eq!(first.read().unwrap(), r#"{"current":5}"#);
+    }
+
+    #[test]
+    fn redis_renewal_test() {
+        let url = serve();
+        let lease = Duration::from_millis(300);
+
+        let mut first = RedisRecorder::new(&url, "mex").unwrap().with_lease(lease);
+        let mut second = RedisRecorder::new(&url, "mex").unwrap().with_lease(lease);
+        first.acquire(Duration::from_secs(0)).unwrap();
+        // acquiring again keeps the lease held
+        assert!(first.try_acquire().unwrap());
+
+        // the lease outlives its duration without writes
+        match second.acquire(Duration::from_millis(900)) {
+            Err(Error::Locked(_)) => (),
+            r => panic!("unexpected: {:?}", r),
+        }
+        first.out(r#"{"current":3}"#).unwrap();
+
+        first.release().unwrap();
+        second.acquire(Duration::from_secs(0)).unwrap();
+        match first.out(r#"{"current":4}"#) {
+            Err(Error::Locked(_)) => (),
+            r => panic!("unexpected: {:?}", r),
+        }
+    }
+}
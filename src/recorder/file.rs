@@ -1,14 +1,93 @@
+use std::fs;
 use std::fs::OpenOptions;
+use std::io::ErrorKind;
 use std::io::Read;
 use std::io::Write;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 
-use log::trace;
+use log::{trace, warn};
 
+use crate::error::Error;
 use crate::error::Result;
 use crate::recorder::ProgressRecorder;
 
+/// Returns a path with a suffix appended, e.g. `progress.txt.bak`.
+fn with_suffix(path: &Path, suffix: &str) -> PathBuf {
+    let mut s = path.as_os_str().to_owned();
+    s.push(suffix);
+    PathBuf::from(s)
+}
+
+/// Flushes renames in the directory of a given path.
+#[cfg(unix)]
+fn sync_dir(path: &Path) -> Result<()> {
+    let dir = match path.parent() {
+        Some(p) if !p.as_os_str().is_empty() => p,
+        _ => Path::new("."),
+    };
+    fs::File::open(dir)?.sync_all()?;
+    Ok(())
+}
+
+/// Directories cannot be opened as files on other platforms, so this does nothing.
+#[cfg(not(unix))]
+fn sync_dir(_path: &Path) -> Result<()> {
+    Ok(())
+}
+
+/// Writes contents into a file atomically, through a temporary file which is fsynced and renamed.
+/// If `backup` is given, the previous file is moved there.
+pub(crate) fn write_atomically(path: &Path, contents: &[u8], backup: Option<&Path>) -> Result<()> {
+    let tmp = with_suffix(path, ".tmp");
+    {
+        let mut file = OpenOptions::new()
+            .create(true)
+            .write(true)
+            .truncate(true)
+            .open(&tmp)?;
+        file.write_all(contents)?;
+        file.sync_all()?;
+    }
+    if let Some(backup) = backup {
+        match fs::rename(path, backup) {
+            Err(ref e) if e.kind() == ErrorKind::NotFound => (),
+            r => r?,
+        }
+    }
+    fs::rename(&tmp, path)?;
+    sync_dir(path)
+}
+
+/// Reads a whole file. This returns `None` if the file does not exist.
+pub(crate) fn read_if_exists(path: &Path) -> Result<Option<String>> {
+    trace!("read from {}", path.display());
+    match OpenOptions::new().read(true).open(path) {
+        Ok(mut file) => {
+            let mut s = String::new();
+            let size = file.read_to_string(&mut s)?;
+            trace!("read: {} ({} bytes)", s, size);
+            Ok(Some(s))
+        }
+        Err(ref e) if e.kind() == ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// Checks that a content of a progress file is a JSON value.
+fn check(path: &Path, s: String) -> Result<String> {
+    if s.trim().is_empty() {
+        return Err(Error::CorruptProgress(format!("{} is empty", path.display())));
+    }
+    serde_json::from_str::<serde_json::Value>(&s)
+        .map(|_| s)
+        .map_err(|e| Error::CorruptProgress(format!("{}: {}", path.display(), e)))
+}
+
 /// A progress recorder on a file.
+///
+/// Progress is written into a temporary file and renamed, so a crash never leaves a partial file.
+/// The previous checkpoint is kept as `<path>.bak`, and it is read when the file is broken.
+/// `read` returns `Error::NoProgress` if neither exists.
 #[derive(Debug)]
 pub struct FileRecorder {
     path: PathBuf,
@@ -21,25 +100,70 @@ impl FileRecorder {
             path
         }
     }
+
+    /// Returns a path of the backup, which has the previous checkpoint.
+    pub fn backup_path(&self) -> PathBuf {
+        with_suffix(&self.path, ".bak")
+    }
 }
 
 impl ProgressRecorder for FileRecorder {
     fn read(&self) -> Result<String> {
-        trace!("read from {}", self.path.display());
-        let mut file = OpenOptions::new().read(true).open(&self.path)?;
-        let mut s = String::new();
-        let size = file.read_to_string(&mut s)?;
-        trace!("read: {} ({} bytes)", s, size);
-        Ok(s)
+        let primary = match read_if_exists(&self.path)? {
+            Some(s) => match check(&self.path, s) {
+                Ok(s) => return Ok(s),
+                Err(e) => Some(e),
+            },
+            None => None,
+        };
+
+        let backup_path = self.backup_path();
+        match read_if_exists(&backup_path)? {
+            Some(s) => {
+                let s = check(&backup_path, s).map_err(|e| primary.unwrap_or(e))?;
+                warn!("{} is unavailable, read from the backup", self.path.display());
+                Ok(s)
+            }
+            None => Err(primary.unwrap_or(Error::NoProgress)),
+        }
     }
 
     fn out(&mut self, json: &str) -> Result<()> {
-        let mut file = OpenOptions::new()
-            .create(true)
-            .write(true)
-            .truncate(true)
-            .open(self.path.clone())?;
-        file.write_all(json.as_bytes())?;
-        Ok(())
+        write_atomically(&self.path, json.as_bytes(), Some(&self.backup_path()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn file_recorder_backup_test() {
+        let path = std::env::temp_dir().join(format!("pikmin-progress-{}.json", std::process::id()));
+        let mut recorder = FileRecorder::new(path.clone());
+        let _ = fs::remove_file(&path);
+        let _ = fs::remove_file(recorder.backup_path());
+
+        match recorder.read() {
+            Err(Error::NoProgress) => (),
+            r => panic!("unexpected: {:?}", r),
+        }
+
+        recorder.out(r#"{"current":1}"#).unwrap();
+        recorder.out(r#"{"current":2}"#).unwrap();
+        assert_eq!(recorder.read().unwrap(), r#"{"current":2}"#);
+
+        // a broken file falls back to the previous checkpoint
+        fs::write(&path, r#"{"curr"#).unwrap();
+        assert_eq!(recorder.read().unwrap(), r#"{"current":1}"#);
+
+        fs::write(recorder.backup_path(), "").unwrap();
+        match recorder.read() {
+            Err(Error::CorruptProgress(_)) => (),
+            r => panic!("unexpected: {:?}", r),
+        }
+
+        fs::remove_file(&path).unwrap();
+        fs::remove_file(recorder.backup_path()).unwrap();
     }
 }
@@ -1,9 +1,19 @@
 use crate::error::Result;
 
+/// A progress recorder on MySQL.
+pub mod db_mysql;
+/// A progress recorder on Redis, with a lease lock.
+#[cfg(feature = "redis")]
+pub mod db_redis;
 /// A progress recorder on a file.
 pub mod file;
 /// A progress recorder on memory.
 pub mod memory;
+/// A progress recorder on SQLite.
+#[cfg(feature = "sqlite")]
+pub mod sqlite;
+/// A progress store for many jobs in a single file.
+pub mod store;
 
 /// An abstraction of a progress recorder, with reading and writing.
 pub trait ProgressRecorder {
@@ -0,0 +1,79 @@
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+
+use chrono::Utc;
+use log::trace;
+use rusqlite::{params, Connection, OptionalExtension};
+
+use crate::error::Result;
+use crate::recorder::ProgressRecorder;
+use crate::writer::db_sqlite::{lock, SharedConnection};
+
+const CREATE_TABLE_STMT: &str = "CREATE TABLE IF NOT EXISTS pikmin_progress ( \
+    job TEXT NOT NULL PRIMARY KEY, progress TEXT NOT NULL, updated_at TEXT NOT NULL );";
+
+/// A progress recorder on an SQLite table, keyed by a job name.
+///
+/// Use `SQLiteWriter::recorder` to share the database with a writer,
+/// and `SQLiteWriter::with_recorder` to commit trades and progress in one transaction.
+#[derive(Debug)]
+pub struct SQLiteRecorder {
+    job: String,
+    table_created: bool,
+    shared: SharedConnection,
+}
+
+impl SQLiteRecorder {
+    /// Creates a recorder on its own connection to a given database file.
+    pub fn open<P: AsRef<Path>>(path: P, job: &str) -> Result<Self> {
+        trace!("open {}", path.as_ref().display());
+        let conn = Connection::open(path)?;
+        Ok(Self::with_connection(Arc::new(Mutex::new(conn)), job))
+    }
+
+    pub(crate) fn with_connection(shared: SharedConnection, job: &str) -> Self {
+        Self {
+            job: job.to_owned(),
+            table_created: false,
+            shared,
+        }
+    }
+
+    pub(crate) fn create_table(&mut self) -> Result<()> {
+        if !self.table_created {
+            lock(&self.shared).execute_batch(CREATE_TABLE_STMT)?;
+            self.table_created = true;
+        }
+        Ok(())
+    }
+
+    /// Writes progress with a connection, e.g. in a transaction of `SQLiteTransactionalWriter`.
+    pub(crate) fn write_progress(&self, conn: &Connection, json: &str) -> Result<()> {
+        conn.execute(
+            "REPLACE INTO pikmin_progress (job, progress, updated_at) VALUES (?, ?, ?);",
+            params![self.job, json, Utc::now()],
+        )?;
+        Ok(())
+    }
+}
+
+impl ProgressRecorder for SQLiteRecorder {
+    fn read(&self) -> Result<String> {
+        let conn = lock(&self.shared);
+        conn.execute_batch(CREATE_TABLE_STMT)?;
+        let progress = conn
+            .query_row(
+                "SELECT progress FROM pikmin_progress WHERE job = ?;",
+                params![self.job],
+                |r| r.get(0),
+            )
+            .optional()?;
+        trace!("read: {:?}", progress);
+        Ok(progress.unwrap_or_default())
+    }
+
+    fn out(&mut self, json: &str) -> Result<()> {
+        self.create_table()?;
+        self.write_progress(&lock(&self.shared), json)
+    }
+}
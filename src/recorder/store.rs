@@ -0,0 +1,209 @@
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex, MutexGuard};
+
+use chrono::{DateTime, Utc};
+use log::{info, trace};
+use serde_derive::{Deserialize, Serialize};
+
+use crate::downloader::progress::Progress;
+use crate::error::Error;
+use crate::error::Result;
+use crate::recorder::file::FileRecorder;
+use crate::recorder::ProgressRecorder;
+
+/// Progress of a job in the store.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+struct Entry {
+    progress: serde_json::Value,
+    updated_at: DateTime<Utc>,
+}
+
+/// Progress of a job, listed by `ProgressStore::jobs`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct JobProgress {
+    /// A name of the job.
+    pub job: String,
+    /// Recorded progress, which is a `Progress` envelope.
+    pub progress: serde_json::Value,
+    /// An ID for the next fetch in the progress, e.g. `{"current":12}`.
+    /// `None` if the progress cannot be parsed.
+    pub current: Option<serde_json::Value>,
+    /// When the progress was recorded.
+    pub updated_at: DateTime<Utc>,
+}
+
+#[derive(Debug)]
+struct Inner {
+    file: FileRecorder,
+    jobs: BTreeMap<String, Entry>,
+}
+
+impl Inner {
+    fn save(&mut self) -> Result<()> {
+        let json = serde_json::to_string_pretty(&self.jobs)?;
+        self.file.out(&json)
+    }
+}
+
+/// A progress store for many jobs in a single JSON file, keyed by job names.
+///
+/// The file is written in the same way as `FileRecorder`, so it survives a crash
+/// and keeps a backup. A store can be cloned and shared by threads, but not by processes.
+///
+/// # Example
+///
+/// ```no_run
+/// use std::path::PathBuf;
+/// use pikmin::recorder::store::ProgressStore;
+///
+/// let store = ProgressStore::open(PathBuf::from("/tmp/progress.json")).unwrap();
+/// let mut recorder = store.recorder("liquid");
+/// // downloader.run(&mut writer, &mut recorder)
+/// for job in store.jobs() {
+///     println!("{}: {:?} at {}", job.job, job.current, job.updated_at);
+/// }
+/// ```
+#[derive(Debug, Clone)]
+pub struct ProgressStore {
+    inner: Arc<Mutex<Inner>>,
+}
+
+impl ProgressStore {
+    /// Opens a store with a given path. The file is created at the first write.
+    pub fn open(path: PathBuf) -> Result<Self> {
+        let file = FileRecorder::new(path);
+        let jobs = match file.read() {
+            Ok(s) => serde_json::from_str(&s).map_err(|e| Error::CorruptProgress(e.to_string()))?,
+            Err(Error::NoProgress) => BTreeMap::new(),
+            Err(e) => return Err(e),
+        };
+        trace!("{} jobs in the store", jobs.len());
+
+        Ok(ProgressStore {
+            inner: Arc::new(Mutex::new(Inner { file, jobs })),
+        })
+    }
+
+    fn lock(&self) -> MutexGuard<'_, Inner> {
+        self.inner.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Creates a progress recorder for a job.
+    pub fn recorder(&self, job: &str) -> KeyedRecorder {
+        KeyedRecorder {
+            store: self.clone(),
+            job: job.to_owned(),
+        }
+    }
+
+    /// Returns progress of all jobs, ordered by their names.
+    pub fn jobs(&self) -> Vec<JobProgress> {
+        self.lock()
+            .jobs
+            .iter()
+            .map(|(job, e)| JobProgress {
+                job: job.clone(),
+                progress: e.progress.clone(),
+                current: Progress::<serde_json::Value>::parse(&e.progress.to_string())
+                    .ok()
+                    .map(|p| p.id),
+                updated_at: e.updated_at,
+            })
+            .collect()
+    }
+
+    /// Removes progress of a job, so that it starts from the beginning.
+    /// Returns false if the job is not found.
+    pub fn reset(&self, job: &str) -> Result<bool> {
+        let mut inner = self.lock();
+        if inner.jobs.remove(job).is_none() {
+            return Ok(false);
+        }
+        inner.save()?;
+        info!("reset progress of {}", job);
+        Ok(true)
+    }
+}
+
+/// A progress recorder for a job in `ProgressStore`.
+#[derive(Debug, Clone)]
+pub struct KeyedRecorder {
+    store: ProgressStore,
+    job: String,
+}
+
+impl ProgressRecorder for KeyedRecorder {
+    fn read(&self) -> Result<String> {
+        match self.store.lock().jobs.get(&self.job) {
+            Some(e) => Ok(e.progress.to_string()),
+            None => Err(Error::NoProgress),
+        }
+    }
+
+    fn out(&mut self, json: &str) -> Result<()> {
+        let entry = Entry {
+            progress: serde_json::from_str(json)?,
+            updated_at: Utc::now(),
+        };
+        let mut inner = self.store.lock();
+        inner.jobs.insert(self.job.clone(), entry);
+        inner.save()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    fn progress(current: u64) -> String {
+        format!(
+            r#"{{"version":1,"downloader":"mock","exchange":"ex","symbol":"BTC","range":["0","9"],"updated_at":"2019-01-01T00:00:00Z","id":{{"current":{}}}}}"#,
+            current
+        )
+    }
+
+    #[test]
+    fn progress_store_test() {
+        let path = std::env::temp_dir().join(format!("pikmin-store-{}.json", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+
+        let store = ProgressStore::open(path.clone()).unwrap();
+        let mut bf = store.recorder("bf");
+        let mut qn = store.recorder("qn");
+        assert!(bf.read().is_err());
+
+        bf.out(&progress(3)).unwrap();
+        qn.out(&progress(5)).unwrap();
+        qn.out(&progress(7)).unwrap();
+        assert_eq!(
+            Progress::<serde_json::Value>::parse(&bf.read().unwrap())
+                .unwrap()
+                .id,
+            json!({"current": 3})
+        );
+
+        let store = ProgressStore::open(path.clone()).unwrap();
+        let jobs = store.jobs();
+        assert_eq!(
+            jobs.iter()
+                .map(|j| (j.job.as_str(), j.current.clone()))
+                .collect::<Vec<_>>(),
+            vec![
+                ("bf", Some(json!({"current": 3}))),
+                ("qn", Some(json!({"current": 7})))
+            ],
+        );
+        assert_eq!(jobs[0].progress.to_string(), bf.read().unwrap());
+
+        assert!(store.reset("bf").unwrap());
+        assert!(!store.reset("bf").unwrap());
+        let store = ProgressStore::open(path.clone()).unwrap();
+        assert_eq!(store.jobs().len(), 1);
+
+        std::fs::remove_file(&path).unwrap();
+        let _ = std::fs::remove_file(FileRecorder::new(path).backup_path());
+    }
+}
@@ -0,0 +1,372 @@
+use std::collections::BTreeMap;
+use std::fmt;
+use std::net::{SocketAddr, ToSocketAddrs};
+use std::sync::{Arc, Condvar, Mutex, MutexGuard};
+
+use chrono::{DateTime, Utc};
+use log::info;
+use serde_derive::Serialize;
+
+use crate::downloader::report::{RunObserver, RunReport, RunState};
+use crate::error::Error;
+use crate::error::Result;
+use crate::http::{self, Request, Response};
+use crate::recorder::ProgressRecorder;
+
+/// A status of a job, listed by `Status::jobs`.
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct JobStatus {
+    /// A name of the job.
+    pub job: String,
+    /// The last state, or `None` if the job has not started yet.
+    pub state: Option<RunState>,
+    /// Whether the job is paused by `Status::pause`.
+    pub paused: bool,
+    /// Whether the job is requested to stop by `Status::stop`.
+    pub stopping: bool,
+    /// The last error, including retried ones.
+    pub last_error: Option<String>,
+    /// Progress read from the recorder, if any. See `StatusObserver::with_recorder`.
+    pub progress: Option<serde_json::Value>,
+    /// When the state was updated.
+    pub updated_at: DateTime<Utc>,
+}
+
+/// A recorder shared with readers of statuses, which read it without locking the jobs.
+type SharedRecorder = Arc<Mutex<Box<dyn ProgressRecorder + Send>>>;
+
+struct Entry {
+    state: Option<RunState>,
+    paused: bool,
+    stopping: bool,
+    last_error: Option<String>,
+    updated_at: DateTime<Utc>,
+    recorder: Option<SharedRecorder>,
+}
+
+impl fmt::Debug for Entry {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        // recorders don't implement `Debug`
+        f.debug_struct("Entry")
+            .field("state", &self.state)
+            .field("paused", &self.paused)
+            .field("stopping", &self.stopping)
+            .field("last_error", &self.last_error)
+            .field("updated_at", &self.updated_at)
+            .finish()
+    }
+}
+
+impl Entry {
+    fn new() -> Self {
+        Entry {
+            state: None,
+            paused: false,
+            stopping: false,
+            last_error: None,
+            updated_at: Utc::now(),
+            recorder: None,
+        }
+    }
+
+    /// Returns a status without progress, and the recorder to read it.
+    fn to_status(&self, job: &str) -> (JobStatus, Option<SharedRecorder>) {
+        let status = JobStatus {
+            job: job.to_owned(),
+            state: self.state,
+            paused: self.paused,
+            stopping: self.stopping,
+            last_error: self.last_error.clone(),
+            progress: None,
+            updated_at: self.updated_at,
+        };
+        (status, self.recorder.clone())
+    }
+}
+
+/// Reads progress into a status. This must be called without locking the jobs,
+/// since reading a recorder may take a while, e.g. over the network.
+fn with_progress((mut status, recorder): (JobStatus, Option<SharedRecorder>)) -> JobStatus {
+    status.progress = recorder
+        .and_then(|r| r.lock().unwrap_or_else(|e| e.into_inner()).read().ok())
+        .and_then(|s| serde_json::from_str(&s).ok());
+    status
+}
+
+#[derive(Debug, Default)]
+struct Inner {
+    jobs: Mutex<BTreeMap<String, Entry>>,
+    changed: Condvar,
+}
+
+/// States of jobs, with controls to pause, resume and stop them.
+///
+/// Each job is supervised by a `StatusObserver` passed to `Downloader::run_with_observer`.
+/// `serve` exposes the following endpoints:
+///
+/// * `GET /healthz`: always `200`, for liveness checks
+/// * `GET /readyz`: `503` if a job has failed, for readiness checks
+/// * `GET /jobs`, `GET /jobs/<job>`: statuses in JSON
+/// * `POST /jobs/<job>/pause`, `/resume`, `/stop`: controls
+///
+/// A paused job waits before its next batch. A stopped job returns `StopReason::Stopped`
+/// before its next batch, and keeps stopping in later runs until it is resumed.
+///
+/// # Example
+///
+/// ```no_run
+/// use std::path::PathBuf;
+/// use pikmin::FileRecorder;
+/// use pikmin::status::Status;
+///
+/// let status = Status::new();
+/// status.serve("127.0.0.1:9899").unwrap();
+/// let path = PathBuf::from("/tmp/qn-progress.txt");
+/// let observer = status.observer("liquid").with_recorder(FileRecorder::new(path));
+/// // downloader.run_with_observer(&mut writer, &mut recorder, observer)
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct Status {
+    inner: Arc<Inner>,
+}
+
+impl Status {
+    /// Creates an empty status.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn lock(&self) -> MutexGuard<'_, BTreeMap<String, Entry>> {
+        self.inner.jobs.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn update<T>(&self, job: &str, f: impl FnOnce(&mut Entry) -> T) -> Option<T> {
+        let result = self.lock().get_mut(job).map(f);
+        self.inner.changed.notify_all();
+        result
+    }
+
+    /// Creates an observer which reports the status of a job.
+    pub fn observer(&self, job: &str) -> StatusObserver {
+        self.lock().entry(job.to_owned()).or_insert_with(Entry::new);
+        StatusObserver {
+            status: self.clone(),
+            job: job.to_owned(),
+        }
+    }
+
+    /// Returns statuses of all jobs, ordered by their names.
+    pub fn jobs(&self) -> Vec<JobStatus> {
+        let statuses: Vec<_> = self.lock().iter().map(|(job, e)| e.to_status(job)).collect();
+        statuses.into_iter().map(with_progress).collect()
+    }
+
+    /// Returns a status of a job.
+    pub fn job(&self, job: &str) -> Option<JobStatus> {
+        let status = self.lock().get(job).map(|e| e.to_status(job));
+        status.map(with_progress)
+    }
+
+    /// Pauses a job before its next batch. Returns false if the job is not found.
+    pub fn pause(&self, job: &str) -> bool {
+        info!("pause {}", job);
+        self.update(job, |e| e.paused = true).is_some()
+    }
+
+    /// Resumes a paused or stopped job. Returns false if the job is not found.
+    pub fn resume(&self, job: &str) -> bool {
+        info!("resume {}", job);
+        self.update(job, |e| {
+            e.paused = false;
+            e.stopping = false;
+        })
+            .is_some()
+    }
+
+    /// Stops a job before its next batch, even if it is paused.
+    /// Returns false if the job is not found.
+    pub fn stop(&self, job: &str) -> bool {
+        info!("stop {}", job);
+        self.update(job, |e| e.stopping = true).is_some()
+    }
+
+    fn handle(&self, req: &Request) -> Response {
+        let segments: Vec<&str> = req.path.trim_matches('/').split('/').collect();
+        match (req.method.as_str(), segments.as_slice()) {
+            ("GET", ["healthz"]) => Response::new(200, "text/plain", "ok\n".to_owned()),
+            ("GET", ["readyz"]) => {
+                let failed: Vec<String> = self
+                    .lock()
+                    .iter()
+                    .filter(|(_, e)| e.state == Some(RunState::Failed))
+                    .map(|(job, _)| job.clone())
+                    .collect();
+                if failed.is_empty() {
+                    Response::new(200, "text/plain", "ready\n".to_owned())
+                } else {
+                    Response::new(503, "text/plain", format!("failed: {}\n", failed.join(", ")))
+                }
+            }
+            ("GET", ["jobs"]) => json(&self.jobs()),
+            ("GET", ["jobs", job]) => self.job(job).map_or_else(Response::not_found, |s| json(&s)),
+            ("POST", ["jobs", job, action]) => {
+                let found = match *action {
+                    "pause" => self.pause(job),
+                    "resume" => self.resume(job),
+                    "stop" => self.stop(job),
+                    _ => false,
+                };
+                match self.job(job) {
+                    Some(ref s) if found => json(s),
+                    _ => Response::not_found(),
+                }
+            }
+            _ => Response::not_found(),
+        }
+    }
+
+    /// Serves the endpoints over HTTP on a background thread, e.g. at `127.0.0.1:9899`.
+    /// Returns the bound address.
+    pub fn serve(&self, addr: impl ToSocketAddrs) -> Result<SocketAddr> {
+        let status = self.clone();
+        http::serve(addr, move |req| status.handle(req))
+    }
+}
+
+fn json<T: serde::Serialize>(value: &T) -> Response {
+    match serde_json::to_string(value) {
+        Ok(body) => Response::new(200, "application/json", body),
+        Err(e) => Response::new(500, "text/plain", format!("{}\n", e)),
+    }
+}
+
+/// An observer which reports the status of a job to `Status`. See `Status::observer`.
+#[derive(Debug, Clone)]
+pub struct StatusObserver {
+    status: Status,
+    job: String,
+}
+
+impl StatusObserver {
+    /// Sets a recorder to read progress of the job, which is shown in statuses.
+    /// This should read the same progress as the recorder of the run, e.g. a `FileRecorder` with the same path.
+    pub fn with_recorder(self, recorder: impl ProgressRecorder + Send + 'static) -> Self {
+        self.status
+            .update(&self.job, |e| e.recorder = Some(Arc::new(Mutex::new(Box::new(recorder)))));
+        self
+    }
+}
+
+impl RunObserver for StatusObserver {
+    fn on_batch(&mut self, _report: &RunReport) {
+        self.status.update(&self.job, |e| e.updated_at = Utc::now());
+    }
+
+    fn on_state(&mut self, state: RunState) {
+        self.status.update(&self.job, |e| {
+            e.state = Some(state);
+            e.updated_at = Utc::now();
+        });
+    }
+
+    fn on_error(&mut self, error: &Error) {
+        self.status
+            .update(&self.job, |e| e.last_error = Some(error.to_string()));
+    }
+
+    fn proceed(&mut self) -> bool {
+        let mut jobs = self.status.lock();
+        loop {
+            match jobs.get(&self.job) {
+                Some(e) if e.stopping => return false,
+                Some(e) if e.paused => (),
+                _ => return true,
+            }
+            jobs = self
+                .status
+                .inner
+                .changed
+                .wait(jobs)
+                .unwrap_or_else(|e| e.into_inner());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{Read, Write};
+    use std::net::TcpStream;
+    use std::sync::mpsc;
+    use std::thread;
+    use std::time::Duration;
+
+    use crate::recorder::memory::MemoryRecorder;
+
+    use super::*;
+
+    fn request(addr: SocketAddr, method: &str, path: &str) -> String {
+        let mut stream = TcpStream::connect(addr).unwrap();
+        write!(stream, "{} {} HTTP/1.1\r\nHost: localhost\r\n\r\n", method, path).unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).unwrap();
+        response
+    }
+
+    #[test]
+    fn status_control_test() {
+        let status = Status::new();
+        let addr = status.serve("127.0.0.1:0").unwrap();
+
+        let mut recorder = MemoryRecorder::default();
+        recorder.out(r#"{"id":{"current":3}}"#).unwrap();
+        let mut observer = status.observer("bf").with_recorder(recorder);
+        observer.on_state(RunState::Sleeping);
+        let response = request(addr, "GET", "/jobs/bf");
+        assert!(response.contains(r#""state":"sleeping""#));
+        assert!(response.contains(r#""progress":{"id":{"current":3}}"#));
+
+        // a paused job waits until it is resumed
+        assert!(request(addr, "POST", "/jobs/bf/pause").starts_with("HTTP/1.1 200 OK"));
+        let (tx, rx) = mpsc::channel();
+        let mut paused = observer.clone();
+        thread::spawn(move || tx.send(paused.proceed()).unwrap());
+        assert!(rx.recv_timeout(Duration::from_millis(200)).is_err());
+        status.resume("bf");
+        assert_eq!(rx.recv_timeout(Duration::from_secs(5)), Ok(true));
+
+        request(addr, "POST", "/jobs/bf/stop");
+        assert!(!observer.proceed());
+        assert!(request(addr, "POST", "/jobs/qn/stop").starts_with("HTTP/1.1 404"));
+
+        assert!(request(addr, "GET", "/readyz").starts_with("HTTP/1.1 200"));
+        observer.on_error(&Error::Locked("bf".to_owned()));
+        observer.on_state(RunState::Failed);
+        assert!(request(addr, "GET", "/readyz").starts_with("HTTP/1.1 503"));
+        assert_eq!(status.jobs()[0].last_error, Some(Error::Locked("bf".to_owned()).to_string()));
+    }
+
+    /// A recorder which updates the status while being read, like a slow recorder racing with an observer.
+    struct Intrusive(Status);
+
+    impl ProgressRecorder for Intrusive {
+        fn read(&self) -> Result<String> {
+            self.0.pause("bf");
+            Ok(r#"{"id":{"current":5}}"#.to_owned())
+        }
+
+        fn out(&mut self, _json: &str) -> Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn status_read_without_lock_test() {
+        let status = Status::new();
+        let _observer = status.observer("bf").with_recorder(Intrusive(status.clone()));
+
+        let job = status.job("bf").unwrap();
+        assert_eq!(job.progress, Some(serde_json::json!({"id": {"current": 5}})));
+        assert!(status.job("bf").unwrap().paused);
+        assert_eq!(status.jobs().len(), 1);
+    }
+}
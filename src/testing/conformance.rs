@@ -0,0 +1,176 @@
+use std::collections::BTreeSet;
+
+use crate::downloader::report::RunReport;
+use crate::downloader::Downloader;
+use crate::error::Result;
+use crate::recorder::ProgressRecorder;
+use crate::testing::MockWriter;
+use crate::writer::Trade;
+
+/// A recorder which keeps all the recorded progress.
+#[derive(Debug)]
+struct History {
+    values: Vec<String>,
+}
+
+impl ProgressRecorder for History {
+    fn read(&self) -> Result<String> {
+        Ok(self.values.last().cloned().unwrap_or_default())
+    }
+
+    fn out(&mut self, json: &str) -> Result<()> {
+        self.values.push(json.to_owned());
+        Ok(())
+    }
+}
+
+/// Runs a downloader from `progress`, and returns written trades and checkpoints,
+/// which are progress recorded after each batch with the number of trades written before it.
+fn download<D: Downloader>(downloader: &D, progress: &str) -> (Vec<Trade>, Vec<(String, usize)>) {
+    let mut writer = MockWriter::new();
+    let mut recorder = History {
+        values: vec![progress.to_owned()],
+    };
+    let mut written = vec![];
+    downloader
+        .run_with_observer(&mut writer, &mut recorder, |r: &RunReport| written.push(r.written as usize))
+        .unwrap_or_else(|e| panic!("{} failed from {:?}: {}", downloader.name(), progress, e));
+    let checkpoints = recorder.values.into_iter().skip(1).zip(written).collect();
+    (writer.store, checkpoints)
+}
+
+fn ids(trades: &[Trade]) -> Vec<&str> {
+    trades.iter().map(|t| t.id.as_str()).collect()
+}
+
+/// Asserts that a downloader keeps the contract of `Downloader` over a synthetic dataset,
+/// where `expected` are IDs of the trades in its range:
+///
+/// * no trades in the range are lost,
+/// * no trades are written twice,
+/// * no trades out of the range are written,
+/// * resuming from the progress recorded after any batch writes the same trades as the rest of the first run.
+///
+/// `run` must return without errors. This runs the downloader again after each batch, so set its sleep to 0.
+/// Downloaders which hold back trades at the latest timestamp, like `LiquidDownloader`,
+/// need newer trades to write them; use `assert_conforms_with` for them.
+///
+/// # Panics
+/// Panics with the broken rule and IDs of the trades.
+///
+/// # Example
+///
+/// ```
+/// use pikmin::testing::{assert_conforms, MockDownloader, RawData};
+///
+/// let downloader = MockDownloader::new(RawData::from_ids(&[1, 3, 5, 7]), 2, 6);
+/// assert_conforms(&downloader, &["3", "5"]);
+/// ```
+pub fn assert_conforms<D: Downloader, S: AsRef<str>>(downloader: &D, expected: &[S]) {
+    assert_conforms_with(downloader, expected, || ());
+}
+
+/// Asserts the same contract as `assert_conforms`, where `arrive` adds trades newer than the dataset
+/// after the first run, as if they are just executed.
+/// Trades in the range held back by the first run must be written by the next run from its progress.
+///
+/// # Panics
+/// Panics with the broken rule and IDs of the trades.
+///
+/// # Example
+///
+/// ```
+/// use std::sync::Arc;
+///
+/// use chrono::{TimeZone, Utc};
+/// use pikmin::LiquidDownloader;
+/// use pikmin::testing::{assert_conforms_with, FakeExchange, FakeTrade};
+///
+/// let at = |secs| Utc.timestamp(1_546_300_800 + secs, 0);
+/// let trades = (1..=4).map(|id| FakeTrade { id, traded_at: at(id as i64) }).collect();
+/// let exchange = Arc::new(FakeExchange::new(trades));
+/// let downloader = LiquidDownloader::new(at(2), at(4))
+///     .with_sleep_millis(0)
+///     .with_transport(exchange.clone());
+/// // the trade at the latest timestamp is written after a newer one arrives
+/// assert_conforms_with(&downloader, &["2", "3", "4"], || {
+///     exchange.push(FakeTrade { id: 5, traded_at: at(3600) })
+/// });
+/// ```
+pub fn assert_conforms_with<D, S, F>(downloader: &D, expected: &[S], arrive: F)
+where
+    D: Downloader,
+    S: AsRef<str>,
+    F: FnOnce(),
+{
+    let name = downloader.name();
+    let (mut trades, checkpoints) = download(downloader, "");
+    for (progress, n) in &checkpoints {
+        let (rest, _) = download(downloader, progress);
+        assert!(
+            rest[..] == trades[*n..],
+            "{} wrote {:?} after resuming from {}, instead of {:?}",
+            name,
+            ids(&rest),
+            progress,
+            ids(&trades[*n..])
+        );
+    }
+
+    arrive();
+    let last = checkpoints.last().map(|(p, _)| p.as_str()).unwrap_or_default();
+    let (later, later_checkpoints) = download(downloader, last);
+    let offset = trades.len();
+    trades.extend(later);
+
+    let mut written = BTreeSet::new();
+    let duplicated: Vec<&str> = ids(&trades).into_iter().filter(|id| !written.insert(*id)).collect();
+    assert!(duplicated.is_empty(), "{} wrote trades twice: {:?}", name, duplicated);
+
+    let expected: BTreeSet<&str> = expected.iter().map(AsRef::as_ref).collect();
+    let lost: Vec<_> = expected.difference(&written).collect();
+    assert!(lost.is_empty(), "{} lost trades in the range: {:?}", name, lost);
+    let out_of_range: Vec<_> = written.difference(&expected).collect();
+    assert!(out_of_range.is_empty(), "{} wrote trades out of the range: {:?}", name, out_of_range);
+
+    for (progress, n) in later_checkpoints {
+        let (rest, _) = download(downloader, &progress);
+        assert!(
+            rest[..] == trades[offset + n..],
+            "{} wrote {:?} after resuming from {}, instead of {:?}",
+            name,
+            ids(&rest),
+            progress,
+            ids(&trades[offset + n..])
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use proptest::collection::btree_set;
+    use proptest::prelude::*;
+
+    use crate::testing::{MockDownloader, RawData};
+
+    use super::*;
+
+    proptest! {
+        #[test]
+        fn mock_conformance_test(
+            ids in btree_set(0u32..200, 0..60),
+            start in 0u32..220,
+            end in 0u32..220,
+            page_size in 1usize..5,
+        ) {
+            let ids: Vec<u32> = ids.into_iter().collect();
+            let expected: Vec<String> = ids
+                .iter()
+                .filter(|&&id| start <= id && id < end)
+                .map(|id| id.to_string())
+                .collect();
+            let downloader = MockDownloader::new(RawData::from_ids(&ids), start, end).with_page_size(page_size);
+            assert_conforms(&downloader, &expected);
+        }
+    }
+}
@@ -0,0 +1,153 @@
+use chrono::offset::TimeZone;
+use chrono::Utc;
+
+use crate::downloader::Downloader;
+use crate::downloader::id::num::OrdID;
+use crate::error::{Error, Result};
+use crate::writer::Trade;
+use crate::writer::Writer;
+
+/// A raw trade of `MockDownloader`. A trade with ID `n` is traded at `n` seconds after the epoch,
+/// with a quantity of `n * 0.1` and a price of `n * 0.01`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct RawData {
+    pub id: u32,
+}
+
+impl RawData {
+    /// Creates raw trades with given IDs.
+    pub fn from_ids(ids: &[u32]) -> Vec<Self> {
+        ids.iter().map(|&id| RawData { id }).collect()
+    }
+}
+
+/// A downloader which fetches trades from a vector in memory, from `start_id` until `end_id`.
+///
+/// Each page has trades with IDs equal to or greater than the current ID, up to the page size.
+/// The next ID is the last ID of a page plus 1, and trades with IDs from `end_id` are not written.
+/// An empty page is `Error::EmptyPage`.
+///
+/// ```
+/// use pikmin::downloader::Downloader;
+/// use pikmin::recorder::memory::MemoryRecorder;
+/// use pikmin::testing::{FaultyWriter, MockDownloader, MockWriter, RawData};
+///
+/// let downloader = MockDownloader::new(RawData::from_ids(&[1, 2, 3, 4]), 1, 5);
+/// let mut writer = FaultyWriter::new(MockWriter::new()).fail_on(&[2]);
+/// let mut recorder = MemoryRecorder::default();
+///
+/// // the second batch fails, and the next run resumes from it
+/// assert!(downloader.run(&mut writer, &mut recorder).is_err());
+/// downloader.run(&mut writer, &mut recorder).unwrap();
+/// assert_eq!(writer.inner().ids(), vec!["1", "2", "3", "4"]);
+/// ```
+#[derive(Debug)]
+pub struct MockDownloader {
+    start_id: u32,
+    end_id: u32,
+    source: Vec<RawData>,
+    page_size: usize,
+    sleep_millis: u64,
+    retries: u32,
+}
+
+impl MockDownloader {
+    /// Creates a downloader with a page size of 2.
+    pub fn new(source: Vec<RawData>, start_id: u32, end_id: u32) -> Self {
+        MockDownloader {
+            source,
+            start_id,
+            end_id,
+            page_size: 2,
+            sleep_millis: 0,
+            retries: 0,
+        }
+    }
+
+    /// Sets the number of trades in a page.
+    pub fn with_page_size(mut self, page_size: usize) -> Self {
+        self.page_size = page_size;
+        self
+    }
+
+    /// Sets milli seconds to sleep between pages. The default is 0.
+    pub fn with_sleep_millis(mut self, millis: u64) -> Self {
+        self.sleep_millis = millis;
+        self
+    }
+
+    /// Sets how many times a batch is retried. See `Downloader::max_retries`.
+    pub fn with_retries(mut self, retries: u32) -> Self {
+        self.retries = retries;
+        self
+    }
+}
+
+impl Downloader for MockDownloader {
+    type IDT = u32;
+    type ID = OrdID<Self::IDT>;
+    type RAW = RawData;
+
+    fn name(&self) -> String {
+        "mock".to_owned()
+    }
+
+    fn start_id(&self) -> Self::IDT {
+        self.start_id
+    }
+
+    fn end_id(&self) -> Self::IDT {
+        self.end_id
+    }
+
+    fn continue_condition(&self, current: &Self::IDT, end: &Self::IDT) -> bool {
+        current < end
+    }
+
+    fn fetch(&self, c: &Self::IDT) -> Result<Vec<Self::RAW>> {
+        let d: Vec<_> = self
+            .source
+            .iter()
+            .cloned()
+            .filter(|e| e.id >= *c)
+            .take(self.page_size)
+            .collect();
+        if d.is_empty() {
+            Err(Error::EmptyPage)
+        } else {
+            Ok(d)
+        }
+    }
+
+    fn convert(&self, v: &Self::RAW) -> Result<Trade> {
+        Ok(Trade {
+            id: format!("{}", v.id),
+            traded_at: Utc.timestamp(v.id.into(), 0),
+            quantity: (v.id as f32) * 0.1,
+            price: (v.id as f32) * 0.01,
+        })
+    }
+
+    fn output(&self, u: Vec<Trade>, writer: &mut impl Writer) -> Result<Self::IDT> {
+        let next = match u.last() {
+            Some(last) => last.id.parse::<u32>()? + 1,
+            None => return Err(Error::EmptyPage),
+        };
+        let mut in_range = Vec::with_capacity(u.len());
+        for trade in u {
+            if trade.id.parse::<u32>()? < self.end_id {
+                in_range.push(trade);
+            }
+        }
+        writer.write(&in_range)?;
+        Ok(next)
+    }
+
+    fn sleep_millis(&self) -> u64 {
+        self.sleep_millis
+    }
+
+    fn max_retries(&self) -> u32 {
+        self.retries
+    }
+}
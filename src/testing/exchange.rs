@@ -0,0 +1,236 @@
+use std::collections::BTreeMap;
+use std::str::FromStr;
+use std::sync::{Mutex, MutexGuard};
+
+use chrono::{DateTime, NaiveDateTime, TimeZone, Utc};
+use serde_json::{json, Value};
+
+use crate::error::Result;
+use crate::transport::{HttpRequest, HttpResponse, Transport};
+
+/// A trade on `FakeExchange`. Pre-composed downloaders convert it into a `Trade` with `id` as its ID.
+#[derive(Debug, Clone, PartialEq)]
+pub struct FakeTrade {
+    pub id: u64,
+    pub traded_at: DateTime<Utc>,
+}
+
+type Params<'a> = BTreeMap<&'a str, &'a str>;
+
+fn param<T: FromStr>(params: &Params, name: &str) -> Option<T> {
+    params.get(name).and_then(|v| v.parse().ok())
+}
+
+fn side<'a>(t: &FakeTrade, buy: &'a str, sell: &'a str) -> &'a str {
+    match t.id % 2 {
+        0 => buy,
+        _ => sell,
+    }
+}
+
+fn price(t: &FakeTrade) -> f64 {
+    400_000.0 + (t.id % 1000) as f64
+}
+
+/// A transport which answers requests of the pre-composed downloaders from trades in memory,
+/// with pagination like bitFlyer, BitMEX and Liquid, without the network.
+///
+/// Unknown endpoints are answered with 404, and invalid queries with 400.
+///
+/// # Example
+///
+/// ```
+/// use std::sync::Arc;
+///
+/// use chrono::{TimeZone, Utc};
+/// use pikmin::BfDownloader;
+/// use pikmin::testing::{assert_conforms, FakeExchange, FakeTrade};
+///
+/// let trades = (1..=10)
+///     .map(|id| FakeTrade { id, traded_at: Utc.timestamp(1_546_300_800 + id as i64, 0) })
+///     .collect();
+/// let downloader = BfDownloader::new(3, 6)
+///     .with_sleep_millis(0)
+///     .with_transport(Arc::new(FakeExchange::new(trades)));
+/// assert_conforms(&downloader, &["3", "4", "5"]);
+/// ```
+#[derive(Debug)]
+pub struct FakeExchange {
+    trades: Mutex<Vec<FakeTrade>>,
+}
+
+impl FakeExchange {
+    /// Creates an exchange with trades. IDs must increase with `traded_at`, like real exchanges.
+    pub fn new(mut trades: Vec<FakeTrade>) -> Self {
+        trades.sort_by_key(|t| t.id);
+        FakeExchange {
+            trades: Mutex::new(trades),
+        }
+    }
+
+    /// Adds a trade executed after the existing ones, while downloaders use this exchange.
+    pub fn push(&self, trade: FakeTrade) {
+        let mut trades = self.trades();
+        trades.push(trade);
+        trades.sort_by_key(|t| t.id);
+    }
+
+    fn trades(&self) -> MutexGuard<'_, Vec<FakeTrade>> {
+        self.trades.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Answers `GET /v1/executions` of bitFlyer: trades before an ID, from the newest.
+    fn bitflyer(&self, params: &Params) -> Option<Value> {
+        let before: u64 = param(params, "before")?;
+        let count: usize = param(params, "count")?;
+        let page = self
+            .trades()
+            .iter()
+            .rev()
+            .filter(|t| t.id < before)
+            .take(count)
+            .map(|t| {
+                json!({
+                    "id": t.id,
+                    "side": side(t, "BUY", "SELL"),
+                    "price": price(t),
+                    "size": 0.01,
+                    "exec_date": t.traded_at.format("%Y-%m-%dT%H:%M:%S%.3f").to_string(),
+                    "buy_child_order_acceptance_id": format!("JRF-{}", t.id),
+                    "sell_child_order_acceptance_id": format!("JRF-{}", t.id),
+                })
+            })
+            .collect();
+        Some(Value::Array(page))
+    }
+
+    /// Answers `GET /api/v1/trade` of BitMEX: trades from a time, skipping `start` trades.
+    fn bitmex(&self, params: &Params) -> Option<Value> {
+        let start_time = params.get("startTime")?;
+        let start_time = NaiveDateTime::parse_from_str(start_time, "%Y-%m-%d %H:%M:%S%.f UTC").ok()?;
+        let start_time = Utc.from_utc_datetime(&start_time);
+        let start: usize = param(params, "start")?;
+        let count: usize = param(params, "count")?;
+        let page = self
+            .trades()
+            .iter()
+            .filter(|t| t.traded_at >= start_time)
+            .skip(start)
+            .take(count)
+            .map(|t| {
+                let size = 1 + t.id % 100;
+                json!({
+                    "timestamp": t.traded_at,
+                    "symbol": params.get("symbol"),
+                    "side": side(t, "Buy", "Sell"),
+                    "size": size,
+                    "price": price(t),
+                    "tickDirection": "ZeroPlusTick",
+                    "trdMatchID": t.id.to_string(),
+                    "grossValue": size * 100_000_000 / price(t) as u64,
+                    "homeNotional": size as f64 / price(t),
+                    "foreignNotional": size,
+                })
+            })
+            .collect();
+        Some(Value::Array(page))
+    }
+
+    /// Answers `GET /executions` of Liquid: trades from a timestamp in seconds.
+    fn liquid(&self, params: &Params) -> Option<Value> {
+        let timestamp: i64 = param(params, "timestamp")?;
+        let limit: usize = param(params, "limit")?;
+        let page = self
+            .trades()
+            .iter()
+            .filter(|t| t.traded_at.timestamp() >= timestamp)
+            .take(limit)
+            .map(|t| {
+                json!({
+                    "id": t.id,
+                    "quantity": "0.01",
+                    "price": price(t).to_string(),
+                    "taker_side": side(t, "buy", "sell"),
+                    "created_at": t.traded_at.timestamp(),
+                })
+            })
+            .collect();
+        Some(Value::Array(page))
+    }
+}
+
+impl Transport for FakeExchange {
+    fn execute(&self, request: &HttpRequest) -> Result<HttpResponse> {
+        let mut parts = request.url.splitn(2, '?');
+        let endpoint = parts.next().unwrap_or_default();
+        let query = parts.next().unwrap_or_default();
+        let params: Params = query
+            .split('&')
+            .filter_map(|p| {
+                let mut kv = p.splitn(2, '=');
+                Some((kv.next()?, kv.next()?))
+            })
+            .collect();
+
+        let body = match (request.method.as_str(), endpoint) {
+            ("GET", "https://api.bitflyer.com/v1/executions") => self.bitflyer(&params),
+            ("GET", "https://www.bitmex.com/api/v1/trade") => self.bitmex(&params),
+            ("GET", "https://api.liquid.com/executions") => self.liquid(&params),
+            _ => {
+                return Ok(HttpResponse {
+                    status: 404,
+                    body: "not found".to_owned(),
+                });
+            }
+        };
+        Ok(match body {
+            Some(body) => HttpResponse {
+                status: 200,
+                body: body.to_string(),
+            },
+            None => HttpResponse {
+                status: 400,
+                body: format!("invalid query: {}", query),
+            },
+        })
+    }
+}
+
+/// Generates trades with a range between two of them, which often ends at the latest trade.
+#[cfg(test)]
+pub(crate) fn trades_in_range() -> impl proptest::strategy::Strategy<Value = (Vec<FakeTrade>, FakeTrade, FakeTrade)> {
+    use chrono::Duration;
+    use proptest::collection::vec;
+    use proptest::prelude::*;
+    use proptest::sample::Index;
+
+    // gaps in milli seconds, including trades at the same time
+    let gap = prop_oneof![Just(0i64), 1..1_000i64, 1_000..5_000i64];
+    let latest = prop_oneof![Just(true), Just(false)];
+    (vec((1..4u64, gap), 1..1_500), any::<Index>(), any::<Index>(), latest).prop_map(|(steps, a, b, latest)| {
+        let mut id = 1_000;
+        let mut traded_at = Utc.ymd(2019, 1, 1).and_hms(0, 0, 0);
+        let trades: Vec<FakeTrade> = steps
+            .into_iter()
+            .map(|(step, gap)| {
+                id += step;
+                traded_at += Duration::milliseconds(gap);
+                FakeTrade { id, traded_at }
+            })
+            .collect();
+        let b = if latest { trades.len() - 1 } else { b.index(trades.len()) };
+        let (a, b) = (a.get(&trades).clone(), trades[b].clone());
+        let (lo, hi) = if a.id <= b.id { (a, b) } else { (b, a) };
+        (trades, lo, hi)
+    })
+}
+
+/// Returns a trade an hour after the latest one of `trades`.
+#[cfg(test)]
+pub(crate) fn later_trade(trades: &[FakeTrade]) -> FakeTrade {
+    let latest = trades.iter().max_by_key(|t| t.id).expect("no trades");
+    FakeTrade {
+        id: latest.id + 1,
+        traded_at: latest.traded_at + chrono::Duration::hours(1),
+    }
+}
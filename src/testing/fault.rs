@@ -0,0 +1,331 @@
+use std::collections::BTreeSet;
+use std::io;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::thread;
+use std::time::Duration;
+
+use crate::downloader::report::Traffic;
+use crate::downloader::Downloader;
+use crate::error::Error;
+use crate::error::Result;
+use crate::recorder::ProgressRecorder;
+use crate::writer::Trade;
+use crate::writer::TransactionalWriter;
+use crate::writer::Writer;
+
+/// Returns an error injected by wrappers in this module, which is retryable.
+pub fn injected_error() -> Error {
+    Error::IO(io::Error::new(io::ErrorKind::TimedOut, "injected fault"))
+}
+
+/// A counter of calls, numbered from 1.
+#[derive(Debug, Default)]
+struct Calls(AtomicU64);
+
+impl Calls {
+    fn next(&self) -> u64 {
+        self.0.fetch_add(1, Ordering::SeqCst) + 1
+    }
+}
+
+/// A writer which fails at given calls of `write`, and otherwise writes to an inner writer.
+/// This also wraps `TransactionalWriter`, counting calls of `write_with_progress`.
+///
+/// ```
+/// use pikmin::testing::{FaultyWriter, MockWriter};
+/// use pikmin::writer::Writer;
+///
+/// // the second write fails
+/// let mut writer = FaultyWriter::new(MockWriter::new()).fail_on(&[2]);
+/// assert!(writer.write(&[]).is_ok());
+/// assert!(writer.write(&[]).is_err());
+/// assert!(writer.write(&[]).is_ok());
+/// ```
+#[derive(Debug)]
+pub struct FaultyWriter<W> {
+    inner: W,
+    calls: Calls,
+    fail_on: BTreeSet<u64>,
+}
+
+impl<W> FaultyWriter<W> {
+    /// Creates a writer which never fails by itself.
+    pub fn new(inner: W) -> Self {
+        FaultyWriter {
+            inner,
+            calls: Calls::default(),
+            fail_on: BTreeSet::new(),
+        }
+    }
+
+    /// Fails at given calls, counted from 1.
+    pub fn fail_on(mut self, calls: &[u64]) -> Self {
+        self.fail_on.extend(calls);
+        self
+    }
+
+    /// Returns the inner writer.
+    pub fn inner(&self) -> &W {
+        &self.inner
+    }
+
+    fn check(&self) -> Result<()> {
+        if self.fail_on.contains(&self.calls.next()) {
+            Err(injected_error())
+        } else {
+            Ok(())
+        }
+    }
+}
+
+impl<W: Writer> Writer for FaultyWriter<W> {
+    fn write(&mut self, trades: &[Trade]) -> Result<u64> {
+        self.check()?;
+        self.inner.write(trades)
+    }
+}
+
+impl<W: ProgressRecorder> ProgressRecorder for FaultyWriter<W> {
+    fn read(&self) -> Result<String> {
+        self.inner.read()
+    }
+
+    fn out(&mut self, json: &str) -> Result<()> {
+        self.inner.out(json)
+    }
+}
+
+impl<W: TransactionalWriter> TransactionalWriter for FaultyWriter<W> {
+    fn write_with_progress(&mut self, trades: &[Trade], json: &str) -> Result<u64> {
+        self.check()?;
+        self.inner.write_with_progress(trades, json)
+    }
+}
+
+/// A recorder which fails at given calls of `read` or `out`, and otherwise uses an inner recorder.
+#[derive(Debug)]
+pub struct FaultyRecorder<R> {
+    inner: R,
+    reads: Calls,
+    outs: Calls,
+    fail_read_on: BTreeSet<u64>,
+    fail_out_on: BTreeSet<u64>,
+}
+
+impl<R> FaultyRecorder<R> {
+    /// Creates a recorder which never fails by itself.
+    pub fn new(inner: R) -> Self {
+        FaultyRecorder {
+            inner,
+            reads: Calls::default(),
+            outs: Calls::default(),
+            fail_read_on: BTreeSet::new(),
+            fail_out_on: BTreeSet::new(),
+        }
+    }
+
+    /// Fails at given calls of `read`, counted from 1.
+    pub fn fail_read_on(mut self, calls: &[u64]) -> Self {
+        self.fail_read_on.extend(calls);
+        self
+    }
+
+    /// Fails at given calls of `out`, counted from 1. Progress is not written at failed calls.
+    pub fn fail_out_on(mut self, calls: &[u64]) -> Self {
+        self.fail_out_on.extend(calls);
+        self
+    }
+
+    /// Returns the inner recorder.
+    pub fn inner(&self) -> &R {
+        &self.inner
+    }
+}
+
+impl<R: ProgressRecorder> ProgressRecorder for FaultyRecorder<R> {
+    fn read(&self) -> Result<String> {
+        if self.fail_read_on.contains(&self.reads.next()) {
+            return Err(injected_error());
+        }
+        self.inner.read()
+    }
+
+    fn out(&mut self, json: &str) -> Result<()> {
+        if self.fail_out_on.contains(&self.outs.next()) {
+            return Err(injected_error());
+        }
+        self.inner.out(json)
+    }
+}
+
+/// A downloader which injects faults into `fetch` of an inner downloader:
+/// failures with a retryable status, delays, and pages with duplicated trades.
+/// Other methods are delegated to the inner downloader.
+#[derive(Debug)]
+pub struct FaultyDownloader<D: Downloader> {
+    inner: D,
+    calls: Calls,
+    fail_on: BTreeSet<u64>,
+    duplicate_on: BTreeSet<u64>,
+    delay: Duration,
+    last_page: Mutex<Vec<D::RAW>>,
+}
+
+impl<D: Downloader> FaultyDownloader<D> {
+    /// Creates a downloader which never fails by itself.
+    pub fn new(inner: D) -> Self {
+        FaultyDownloader {
+            inner,
+            calls: Calls::default(),
+            fail_on: BTreeSet::new(),
+            duplicate_on: BTreeSet::new(),
+            delay: Duration::default(),
+            last_page: Mutex::new(vec![]),
+        }
+    }
+
+    /// Fails at given calls of `fetch`, counted from 1, with `Error::HttpStatus` of 503.
+    pub fn fail_on(mut self, calls: &[u64]) -> Self {
+        self.fail_on.extend(calls);
+        self
+    }
+
+    /// Prepends trades of the previous page to pages at given calls of `fetch`, counted from 1.
+    pub fn duplicate_on(mut self, calls: &[u64]) -> Self {
+        self.duplicate_on.extend(calls);
+        self
+    }
+
+    /// Delays each `fetch`, like a slow response.
+    pub fn with_delay(mut self, delay: Duration) -> Self {
+        self.delay = delay;
+        self
+    }
+}
+
+impl<D> Downloader for FaultyDownloader<D>
+    where
+        D: Downloader,
+        D::RAW: Clone,
+{
+    type IDT = D::IDT;
+    type ID = D::ID;
+    type RAW = D::RAW;
+
+    fn name(&self) -> String {
+        self.inner.name()
+    }
+
+    fn exchange(&self) -> String {
+        self.inner.exchange()
+    }
+
+    fn symbol(&self) -> String {
+        self.inner.symbol()
+    }
+
+    fn start_id(&self) -> Self::IDT {
+        self.inner.start_id()
+    }
+
+    fn end_id(&self) -> Self::IDT {
+        self.inner.end_id()
+    }
+
+    fn continue_condition(&self, current: &Self::IDT, end: &Self::IDT) -> bool {
+        self.inner.continue_condition(current, end)
+    }
+
+    fn fetch(&self, c: &Self::IDT) -> Result<Vec<Self::RAW>> {
+        let call = self.calls.next();
+        thread::sleep(self.delay);
+        if self.fail_on.contains(&call) {
+            return Err(Error::HttpStatus {
+                status: 503,
+                body: "injected fault".to_owned(),
+            });
+        }
+
+        let page = self.inner.fetch(c)?;
+        let mut last_page = self.last_page.lock().unwrap_or_else(|e| e.into_inner());
+        let mut result = if self.duplicate_on.contains(&call) {
+            last_page.clone()
+        } else {
+            vec![]
+        };
+        result.extend(page.iter().cloned());
+        *last_page = page;
+        Ok(result)
+    }
+
+    fn convert(&self, v: &Self::RAW) -> Result<Trade> {
+        self.inner.convert(v)
+    }
+
+    fn output(&self, u: Vec<Trade>, writer: &mut impl Writer) -> Result<Self::IDT> {
+        self.inner.output(u, writer)
+    }
+
+    fn sleep_millis(&self) -> u64 {
+        self.inner.sleep_millis()
+    }
+
+    fn follow_interval(&self) -> Option<Duration> {
+        self.inner.follow_interval()
+    }
+
+    fn max_retries(&self) -> u32 {
+        self.inner.max_retries()
+    }
+
+    fn traffic(&self) -> Option<&Traffic> {
+        self.inner.traffic()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::downloader::report::StopReason;
+    use crate::recorder::memory::MemoryRecorder;
+    use crate::testing::{MockDownloader, MockTransactionalWriter, MockWriter, RawData};
+
+    use super::*;
+
+    #[test]
+    fn resume_after_faults_test() {
+        let source = RawData::from_ids(&[10, 11, 15, 17, 19, 21]);
+        let downloader = MockDownloader::new(source.clone(), 10, 20);
+        let mut writer = FaultyWriter::new(MockWriter::new()).fail_on(&[2]);
+        let mut recorder = FaultyRecorder::new(MemoryRecorder::default()).fail_out_on(&[3]);
+
+        // the second batch fails to be written, and the fourth batch is written without progress,
+        // so they are written again after resuming
+        assert!(downloader.run(&mut writer, &mut recorder).is_err());
+        assert!(downloader.run(&mut writer, &mut recorder).is_err());
+        let report = downloader.run(&mut writer, &mut recorder).unwrap();
+        assert_eq!(report.stop_reason, StopReason::RangeExhausted);
+        assert_eq!(writer.inner().ids(), vec!["10", "11", "15", "17", "19", "19"]);
+
+        let downloader = FaultyDownloader::new(MockDownloader::new(source, 10, 20).with_retries(1))
+            .fail_on(&[1])
+            .duplicate_on(&[3]);
+        let mut writer = MockWriter::new();
+        let report = downloader.run(&mut writer, &mut MemoryRecorder::default()).unwrap();
+        assert_eq!(report.retries, 1);
+        assert_eq!(writer.ids(), vec!["10", "11", "10", "11", "15", "17", "19"]);
+    }
+
+    #[test]
+    fn retry_failed_commit_test() {
+        let source = RawData::from_ids(&[10, 11, 15, 17, 19, 21]);
+        let downloader = MockDownloader::new(source, 10, 20).with_retries(1);
+        let mut writer = FaultyWriter::new(MockTransactionalWriter::default()).fail_on(&[2]);
+
+        // the second batch is rolled back, and committed again by the retry
+        let report = downloader.run_transactional(&mut writer).unwrap();
+        assert_eq!(report.retries, 1);
+        let ids: Vec<&str> = writer.inner().store.iter().map(|t| t.id.as_str()).collect();
+        assert_eq!(ids, vec!["10", "11", "15", "17", "19"]);
+    }
+}
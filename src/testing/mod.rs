@@ -0,0 +1,16 @@
+pub use self::conformance::{assert_conforms, assert_conforms_with};
+pub use self::downloader::{MockDownloader, RawData};
+pub use self::exchange::{FakeExchange, FakeTrade};
+pub use self::fault::{FaultyDownloader, FaultyRecorder, FaultyWriter};
+pub use self::writer::{MockTransactionalWriter, MockWriter};
+
+/// A harness to check the contract of downloaders.
+pub mod conformance;
+/// A downloader from trades in memory.
+pub mod downloader;
+/// A fake exchange to run the pre-composed downloaders offline.
+pub mod exchange;
+/// Wrappers which inject faults.
+pub mod fault;
+/// Writers into memory.
+pub mod writer;
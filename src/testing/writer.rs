@@ -0,0 +1,60 @@
+use crate::error::Result;
+use crate::recorder::ProgressRecorder;
+use crate::writer::Trade;
+use crate::writer::TransactionalWriter;
+use crate::writer::Writer;
+
+/// A writer which keeps trades in memory.
+#[derive(Debug, Default)]
+pub struct MockWriter {
+    /// Written trades, in the written order.
+    pub store: Vec<Trade>,
+}
+
+impl MockWriter {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns IDs of written trades.
+    pub fn ids(&self) -> Vec<&str> {
+        self.store.iter().map(|t| t.id.as_str()).collect()
+    }
+}
+
+impl Writer for MockWriter {
+    fn write(&mut self, trades: &[Trade]) -> Result<u64> {
+        for v in trades {
+            self.store.push(v.clone());
+        }
+        Ok(trades.len() as u64)
+    }
+}
+
+/// A transactional writer which keeps trades and progress in memory.
+#[derive(Debug, Default)]
+pub struct MockTransactionalWriter {
+    /// Written trades, in the written order.
+    pub store: Vec<Trade>,
+    /// Recorded progress.
+    pub progress: String,
+}
+
+impl ProgressRecorder for MockTransactionalWriter {
+    fn read(&self) -> Result<String> {
+        Ok(self.progress.clone())
+    }
+
+    fn out(&mut self, json: &str) -> Result<()> {
+        self.progress = String::from(json);
+        Ok(())
+    }
+}
+
+impl TransactionalWriter for MockTransactionalWriter {
+    fn write_with_progress(&mut self, trades: &[Trade], json: &str) -> Result<u64> {
+        self.store.extend_from_slice(trades);
+        self.progress = String::from(json);
+        Ok(trades.len() as u64)
+    }
+}
@@ -0,0 +1,214 @@
+use std::path::PathBuf;
+use std::sync::{Mutex, MutexGuard};
+
+use log::{info, trace};
+use serde_derive::{Deserialize, Serialize};
+
+use crate::error::Error;
+use crate::error::Result;
+use crate::recorder::file::{read_if_exists, write_atomically};
+use crate::transport::{HttpRequest, HttpResponse, Transport};
+
+/// A pair of a request and its response. Headers are not recorded, not to leak credentials.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+struct Interaction {
+    method: String,
+    url: String,
+    status: u16,
+    body: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, Default)]
+struct Tape {
+    interactions: Vec<Interaction>,
+}
+
+#[derive(Debug)]
+enum Mode {
+    Record(Box<dyn Transport>),
+    Replay,
+}
+
+#[derive(Debug)]
+struct State {
+    tape: Tape,
+    replayed: Vec<bool>,
+}
+
+/// A transport which records request/response pairs on a JSON file, or replays them offline.
+///
+/// In replay mode, a request is answered by the first interaction with the same method and URL
+/// which is not replayed yet, so the same request can be answered differently in order.
+/// A request without such an interaction fails with `Error::Transport`.
+///
+/// # Example
+///
+/// ```no_run
+/// use std::path::PathBuf;
+/// use std::sync::Arc;
+///
+/// use pikmin::BfDownloader;
+/// use pikmin::transport::ReqwestTransport;
+/// use pikmin::transport::cassette::CassetteTransport;
+///
+/// let path = PathBuf::from("tests/cassettes/bitflyer.json");
+/// // record real responses once
+/// let recording = CassetteTransport::record(path.clone(), ReqwestTransport::new());
+/// let downloader = BfDownloader::new(1000, 1005).with_transport(Arc::new(recording));
+/// // ...and replay them in tests
+/// let replaying = CassetteTransport::replay(path).unwrap();
+/// let downloader = BfDownloader::new(1000, 1005).with_transport(Arc::new(replaying));
+/// ```
+#[derive(Debug)]
+pub struct CassetteTransport {
+    path: PathBuf,
+    mode: Mode,
+    state: Mutex<State>,
+}
+
+impl CassetteTransport {
+    /// Creates a transport which sends requests through `inner` and records them on `path`.
+    /// The file is overwritten at the first request, and written at each request.
+    pub fn record(path: PathBuf, inner: impl Transport + 'static) -> Self {
+        CassetteTransport {
+            path,
+            mode: Mode::Record(Box::new(inner)),
+            state: Mutex::new(State {
+                tape: Tape::default(),
+                replayed: vec![],
+            }),
+        }
+    }
+
+    /// Creates a transport which replays interactions recorded on `path`.
+    pub fn replay(path: PathBuf) -> Result<Self> {
+        let tape: Tape = match read_if_exists(&path)? {
+            Some(s) => serde_json::from_str(&s)?,
+            None => return Err(Error::Transport(format!("no cassette at {}", path.display()))),
+        };
+        trace!("{} interactions in {}", tape.interactions.len(), path.display());
+        let replayed = vec![false; tape.interactions.len()];
+
+        Ok(CassetteTransport {
+            path,
+            mode: Mode::Replay,
+            state: Mutex::new(State { tape, replayed }),
+        })
+    }
+
+    fn lock(&self) -> MutexGuard<'_, State> {
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Returns the number of interactions which are not replayed yet.
+    /// This is useful to check that a test has sent all the recorded requests.
+    pub fn remaining(&self) -> usize {
+        self.lock().replayed.iter().filter(|r| !**r).count()
+    }
+}
+
+impl Transport for CassetteTransport {
+    fn execute(&self, request: &HttpRequest) -> Result<HttpResponse> {
+        match self.mode {
+            Mode::Record(ref inner) => {
+                let response = inner.execute(request)?;
+                let mut state = self.lock();
+                state.tape.interactions.push(Interaction {
+                    method: request.method.clone(),
+                    url: request.url.clone(),
+                    status: response.status,
+                    body: response.body.clone(),
+                });
+                let json = serde_json::to_string_pretty(&state.tape)?;
+                write_atomically(&self.path, json.as_bytes(), None)?;
+                info!("recorded {} {} on {}", request.method, request.url, self.path.display());
+                Ok(response)
+            }
+            Mode::Replay => {
+                let mut state = self.lock();
+                let State {
+                    ref tape,
+                    ref mut replayed,
+                } = *state;
+                let found = tape.interactions.iter().zip(replayed.iter_mut()).find(|(i, r)| {
+                    !**r && i.method == request.method && i.url == request.url
+                });
+                match found {
+                    Some((interaction, replayed)) => {
+                        *replayed = true;
+                        trace!("replay {} {}", request.method, request.url);
+                        Ok(HttpResponse {
+                            status: interaction.status,
+                            body: interaction.body.clone(),
+                        })
+                    }
+                    None => Err(Error::Transport(format!(
+                        "{} {} is not recorded in {}",
+                        request.method,
+                        request.url,
+                        self.path.display()
+                    ))),
+                }
+            }
+        }
+    }
+}
+
+/// Replays a cassette in `tests/cassettes`, e.g. `fixture("bitflyer")`.
+#[cfg(test)]
+pub(crate) fn fixture(name: &str) -> std::sync::Arc<CassetteTransport> {
+    let path = PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+        .join("tests/cassettes")
+        .join(format!("{}.json", name));
+    std::sync::Arc::new(CassetteTransport::replay(path).unwrap())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Answers with the URL and the number of requests so far.
+    #[derive(Debug, Default)]
+    struct Echo {
+        count: Mutex<u16>,
+    }
+
+    impl Transport for Echo {
+        fn execute(&self, request: &HttpRequest) -> Result<HttpResponse> {
+            let mut count = self.count.lock().unwrap();
+            *count += 1;
+            Ok(HttpResponse {
+                status: 200,
+                body: format!("{} #{}", request.url, count),
+            })
+        }
+    }
+
+    #[test]
+    fn cassette_record_replay_test() {
+        let path = std::env::temp_dir().join(format!("pikmin-cassette-{}.json", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+
+        let recording = CassetteTransport::record(path.clone(), Echo::default());
+        let a = HttpRequest::get("http://a/").header("X-Key", "secret");
+        let b = HttpRequest::get("http://b/");
+        assert_eq!(recording.execute(&a).unwrap().body, "http://a/ #1");
+        assert_eq!(recording.execute(&b).unwrap().body, "http://b/ #2");
+        assert_eq!(recording.execute(&a).unwrap().body, "http://a/ #3");
+        assert!(!std::fs::read_to_string(&path).unwrap().contains("secret"));
+
+        // the same requests are answered in the recorded order, regardless of other requests
+        let replaying = CassetteTransport::replay(path.clone()).unwrap();
+        assert_eq!(replaying.execute(&a).unwrap().body, "http://a/ #1");
+        assert_eq!(replaying.execute(&a).unwrap().body, "http://a/ #3");
+        assert_eq!(replaying.remaining(), 1);
+        match replaying.execute(&a) {
+            Err(Error::Transport(_)) => (),
+            r => panic!("unexpected: {:?}", r),
+        }
+        assert_eq!(replaying.execute(&b).unwrap().body, "http://b/ #2");
+        assert_eq!(replaying.remaining(), 0);
+
+        std::fs::remove_file(&path).unwrap();
+    }
+}
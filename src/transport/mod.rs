@@ -0,0 +1,95 @@
+use std::fmt;
+
+use log::trace;
+use reqwest::{Client, Method};
+
+use crate::error::Error;
+use crate::error::Result;
+
+/// A transport to record and replay responses on a file.
+pub mod cassette;
+
+/// An HTTP request to an exchange.
+#[derive(Debug, Clone, PartialEq)]
+pub struct HttpRequest {
+    /// A method, e.g. `GET`.
+    pub method: String,
+    /// A full URL including the query.
+    pub url: String,
+    /// Headers as pairs of names and values.
+    pub headers: Vec<(String, String)>,
+}
+
+impl HttpRequest {
+    /// Creates a `GET` request without headers.
+    pub fn get(url: &str) -> Self {
+        HttpRequest {
+            method: "GET".to_owned(),
+            url: url.to_owned(),
+            headers: vec![],
+        }
+    }
+
+    /// Adds a header.
+    pub fn header(mut self, name: &str, value: &str) -> Self {
+        self.headers.push((name.to_owned(), value.to_owned()));
+        self
+    }
+}
+
+/// An HTTP response from an exchange.
+#[derive(Debug, Clone, PartialEq)]
+pub struct HttpResponse {
+    /// A status code.
+    pub status: u16,
+    /// A body as text.
+    pub body: String,
+}
+
+/// An abstraction of a way to send requests to exchanges, under the API clients.
+/// Use `with_transport` of the pre-composed downloaders to replace it.
+pub trait Transport: fmt::Debug + Send + Sync {
+    /// Sends a request and returns its response, whatever its status is.
+    fn execute(&self, request: &HttpRequest) -> Result<HttpResponse>;
+}
+
+/// A transport which sends requests over the network with reqwest. This is the default.
+#[derive(Debug)]
+pub struct ReqwestTransport {
+    client: Client,
+}
+
+impl Default for ReqwestTransport {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ReqwestTransport {
+    /// Creates a transport with a default client.
+    pub fn new() -> Self {
+        Self::with_client(Client::new())
+    }
+
+    /// Creates a transport with a given client, e.g. with a proxy or a timeout.
+    pub fn with_client(client: Client) -> Self {
+        ReqwestTransport { client }
+    }
+}
+
+impl Transport for ReqwestTransport {
+    fn execute(&self, request: &HttpRequest) -> Result<HttpResponse> {
+        trace!("{} {}", request.method, request.url);
+        let method = Method::from_bytes(request.method.as_bytes())
+            .map_err(|e| Error::Transport(format!("{}: {}", e, request.method)))?;
+        let mut builder = self.client.request(method, &request.url);
+        for (name, value) in &request.headers {
+            builder = builder.header(name.as_str(), value.as_str());
+        }
+        let mut resp = builder.send()?;
+        Ok(HttpResponse {
+            status: resp.status().as_u16(),
+            body: resp.text()?,
+        })
+    }
+}
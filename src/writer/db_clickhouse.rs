@@ -0,0 +1,311 @@
+use log::{info, trace};
+use reqwest::Client;
+use serde_json::json;
+
+use crate::error::Error;
+use crate::error::Result;
+use crate::writer::db_mysql::TableDef;
+use crate::writer::Trade;
+use crate::writer::Writer;
+
+/// An input format of `INSERT` statements for ClickHouse.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ClickHouseFormat {
+    /// A compact binary format. This is the default.
+    RowBinary,
+    /// One JSON object per line.
+    JSONEachRow,
+}
+
+impl ClickHouseFormat {
+    fn name(self) -> &'static str {
+        match self {
+            ClickHouseFormat::RowBinary => "RowBinary",
+            ClickHouseFormat::JSONEachRow => "JSONEachRow",
+        }
+    }
+}
+
+/// An constraint for ClickHouseWriter.
+pub trait ClickHouseWriterElement {
+    /// Returns table definitions for ClickHouse.
+    fn table_def() -> Vec<TableDef>;
+    /// Returns a sorting key, which is also the key to deduplicate rows by `ReplacingMergeTree`.
+    fn order_by() -> Vec<String>;
+    /// Returns a partitioning expression.
+    fn partition_by() -> String;
+    /// Converts a contents into a JSON object for `JSONEachRow`.
+    fn to_json_row(&self) -> serde_json::Value;
+    /// Appends a contents to a buffer for `RowBinary`, in the order of `table_def`.
+    fn write_row_binary(&self, buf: &mut Vec<u8>);
+    /// Returns a string to create a table.
+    fn create_table_stmt(table_name: &str) -> String {
+        let columns = Self::table_def()
+            .iter()
+            .map(|d| format!("{} {}", d.name(), d.definition()))
+            .collect::<Vec<String>>();
+
+        format!(
+            "CREATE TABLE IF NOT EXISTS {} ( {} ) ENGINE = ReplacingMergeTree() PARTITION BY {} ORDER BY ({})",
+            table_name,
+            columns.join(", "),
+            Self::partition_by(),
+            Self::order_by().join(", ")
+        )
+    }
+}
+
+/// Appends a string in the `RowBinary` encoding, prefixed by its length as LEB128.
+fn write_string(buf: &mut Vec<u8>, s: &str) {
+    let mut len = s.len();
+    loop {
+        let byte = (len & 0x7f) as u8;
+        len >>= 7;
+        if len == 0 {
+            buf.push(byte);
+            break;
+        }
+        buf.push(byte | 0x80);
+    }
+    buf.extend_from_slice(s.as_bytes());
+}
+
+/// A writer implementation for ClickHouse, through its HTTP interface.
+///
+/// The table is created as a `ReplacingMergeTree`, so trades written twice are merged
+/// in the background. Use `SELECT ... FINAL` to read deduplicated rows.
+#[derive(Debug)]
+pub struct ClickHouseWriter {
+    table_name: String,
+    url: String,
+    format: ClickHouseFormat,
+    credentials: Option<(String, String)>,
+    table_created: bool,
+    client: Client,
+}
+
+impl ClickHouseWriter {
+    /// Creates a writer with a URL of the HTTP interface (e.g. `http://localhost:8123`) and a table name.
+    pub fn new(table_name: &str, url: &str) -> Self {
+        ClickHouseWriter {
+            table_name: table_name.to_owned(),
+            url: url.to_owned(),
+            format: ClickHouseFormat::RowBinary,
+            credentials: None,
+            table_created: false,
+            client: Client::new(),
+        }
+    }
+
+    /// Sets a format to send trades.
+    pub fn with_format(mut self, format: ClickHouseFormat) -> Self {
+        self.format = format;
+        self
+    }
+
+    /// Sets a user and a password for the HTTP basic authentication.
+    pub fn with_credentials(mut self, user: &str, password: &str) -> Self {
+        self.credentials = Some((user.to_owned(), password.to_owned()));
+        self
+    }
+
+    fn execute(&self, query: &str, body: Vec<u8>) -> Result<()> {
+        trace!("execute: {}", query);
+        let req = self.client.post(&self.url).query(&[("query", query)]);
+        let req = match self.credentials {
+            Some((ref user, ref password)) => req.basic_auth(user, Some(password)),
+            None => req,
+        };
+        let mut resp = req.body(body).send()?;
+        let status = resp.status();
+        if status.is_success() {
+            Ok(())
+        } else {
+            // ClickHouse explains the error in the body
+            Err(Error::HttpStatus {
+                status: status.as_u16(),
+                body: resp.text()?,
+            })
+        }
+    }
+
+    fn create_table<T: ClickHouseWriterElement>(&mut self) -> Result<()> {
+        if !self.table_created {
+            self.execute(&T::create_table_stmt(&self.table_name), vec![])?;
+            self.table_created = true;
+        }
+        Ok(())
+    }
+
+    fn insert<T: ClickHouseWriterElement>(&self, v: &[T]) -> Result<u64> {
+        let mut body = vec![];
+        match self.format {
+            ClickHouseFormat::RowBinary => {
+                for e in v {
+                    e.write_row_binary(&mut body);
+                }
+            }
+            ClickHouseFormat::JSONEachRow => {
+                for e in v {
+                    serde_json::to_writer(&mut body, &e.to_json_row())?;
+                    body.push(b'\n');
+                }
+            }
+        }
+
+        let query = format!(
+            "INSERT INTO {} FORMAT {}",
+            self.table_name,
+            self.format.name()
+        );
+        self.execute(&query, body)?;
+        Ok(v.len() as u64)
+    }
+}
+
+impl ClickHouseWriterElement for Trade {
+    fn table_def() -> Vec<TableDef> {
+        vec![
+            TableDef::new("id", "String"),
+            TableDef::new("traded_at", "DateTime64(3, 'UTC')"),
+            TableDef::new("amount", "Float32"),
+            TableDef::new("price", "Float32"),
+        ]
+    }
+
+    fn order_by() -> Vec<String> {
+        vec!["id".to_owned()]
+    }
+
+    fn partition_by() -> String {
+        "toYYYYMM(traded_at)".to_owned()
+    }
+
+    fn to_json_row(&self) -> serde_json::Value {
+        json!({
+            "id": self.id,
+            "traded_at": self.traded_at.format("%Y-%m-%d %H:%M:%S%.3f").to_string(),
+            "amount": self.quantity,
+            "price": self.price,
+        })
+    }
+
+    fn write_row_binary(&self, buf: &mut Vec<u8>) {
+        write_string(buf, &self.id);
+        buf.extend_from_slice(&self.traded_at.timestamp_millis().to_le_bytes());
+        buf.extend_from_slice(&self.quantity.to_le_bytes());
+        buf.extend_from_slice(&self.price.to_le_bytes());
+    }
+}
+
+impl Writer for ClickHouseWriter {
+    fn write(&mut self, trades: &[Trade]) -> Result<u64> {
+        self.create_table::<Trade>()?;
+        self.insert(trades)
+            .inspect(|num| info!("sent {} data", num))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{BufRead, BufReader, Read, Write};
+    use std::net::TcpListener;
+    use std::thread;
+
+    use chrono::offset::TimeZone;
+    use chrono::Utc;
+
+    use super::*;
+
+    /// Answers requests with given statuses and bodies, and returns pairs of a request line and a body.
+    fn serve(
+        listener: TcpListener,
+        responses: Vec<(u16, &'static str)>,
+    ) -> thread::JoinHandle<Vec<(String, Vec<u8>)>> {
+        thread::spawn(move || {
+            responses
+                .into_iter()
+                .map(|(status, response)| {
+                    let (stream, _) = listener.accept().unwrap();
+                    let mut reader = BufReader::new(stream);
+                    let mut request_line = String::new();
+                    reader.read_line(&mut request_line).unwrap();
+                    let mut length = 0;
+                    loop {
+                        let mut header = String::new();
+                        reader.read_line(&mut header).unwrap();
+                        if header.trim().is_empty() {
+                            break;
+                        }
+                        let lower = header.to_lowercase();
+                        if let Some(v) = lower.strip_prefix("content-length:") {
+                            length = v.trim().parse().unwrap();
+                        }
+                    }
+                    let mut body = vec![0; length];
+                    reader.read_exact(&mut body).unwrap();
+                    write!(
+                        reader.get_mut(),
+                        "HTTP/1.1 {} X\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                        status,
+                        response.len(),
+                        response
+                    )
+                    .unwrap();
+                    (request_line, body)
+                })
+                .collect()
+        })
+    }
+
+    #[test]
+    fn clickhouse_insert_test() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/", listener.local_addr().unwrap());
+        let server = serve(listener, vec![(200, ""); 3]);
+
+        let trade = Trade {
+            id: "ab".to_string(),
+            traded_at: Utc.ymd(2019, 1, 1).and_hms_milli(1, 1, 1, 500),
+            quantity: -0.5,
+            price: 3800.0,
+        };
+
+        let mut writer = ClickHouseWriter::new("trades", &url).with_format(ClickHouseFormat::JSONEachRow);
+        assert_eq!(writer.write(std::slice::from_ref(&trade)).unwrap(), 1);
+        writer.format = ClickHouseFormat::RowBinary;
+        assert_eq!(writer.write(&[trade]).unwrap(), 1);
+
+        let requests = server.join().unwrap();
+        assert!(requests[0].0.contains("ReplacingMergeTree"));
+        assert!(requests[1].0.contains("FORMAT+JSONEachRow"));
+        assert_eq!(
+            String::from_utf8_lossy(&requests[1].1),
+            "{\"amount\":-0.5,\"id\":\"ab\",\"price\":3800.0,\"traded_at\":\"2019-01-01 01:01:01.500\"}\n",
+        );
+        assert!(requests[2].0.contains("FORMAT+RowBinary"));
+        let mut exp = vec![2, b'a', b'b'];
+        exp.extend_from_slice(&1_546_304_461_500_i64.to_le_bytes());
+        exp.extend_from_slice(&(-0.5_f32).to_le_bytes());
+        exp.extend_from_slice(&3800.0_f32.to_le_bytes());
+        assert_eq!(requests[2].1, exp);
+    }
+
+    #[test]
+    fn clickhouse_error_body_test() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/", listener.local_addr().unwrap());
+        let reason = "Code: 60. DB::Exception: Table default.trades doesn't exist.";
+        let server = serve(listener, vec![(404, reason)]);
+
+        let mut writer = ClickHouseWriter::new("trades", &url);
+        match writer.write(&[]) {
+            Err(Error::HttpStatus { status, ref body }) => {
+                assert_eq!(status, 404);
+                assert_eq!(body, reason);
+            }
+            r => panic!("unexpected: {:?}", r),
+        }
+        server.join().unwrap();
+    }
+}
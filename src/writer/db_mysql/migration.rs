@@ -0,0 +1,345 @@
+use log::{info, trace};
+use mysql::{params, Pool};
+
+use crate::error::Error;
+use crate::error::Result;
+use crate::writer::db_mysql::{MySQLWriterElement, TableDef};
+
+const CREATE_VERSION_TABLE_STMT: &str = r"CREATE TABLE IF NOT EXISTS pikmin_schema_version ( table_name VARCHAR(64) NOT NULL PRIMARY KEY, version INT UNSIGNED NOT NULL, applied_at TIMESTAMP(3) NOT NULL );";
+
+/// A column of an existing table, as reported by `information_schema.COLUMNS`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ColumnInfo {
+    /// A name of the column.
+    pub name: String,
+    /// A type of the column, e.g. `varchar(64)`.
+    pub column_type: String,
+    /// Whether the column accepts `NULL`.
+    pub nullable: bool,
+}
+
+/// A difference between an existing table and `MySQLWriterElement::table_def`.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ColumnChange {
+    /// A column which is missing in the table. The 3rd value is the preceding column.
+    Add(String, String, Option<String>),
+    /// A column whose type or nullability differs from the definition.
+    Modify(String, String),
+    /// A column which exists only in the table. This is reported, but never dropped automatically.
+    Extra(String),
+}
+
+impl ColumnChange {
+    /// Returns an `ALTER TABLE` statement to apply this change, if any.
+    pub fn to_sql(&self, table_name: &str) -> Option<String> {
+        match self {
+            ColumnChange::Add(name, def, Some(after)) => Some(format!(
+                "ALTER TABLE {} ADD COLUMN {} {} AFTER {};",
+                table_name, name, def, after
+            )),
+            ColumnChange::Add(name, def, None) => Some(format!(
+                "ALTER TABLE {} ADD COLUMN {} {} FIRST;",
+                table_name, name, def
+            )),
+            ColumnChange::Modify(name, def) => Some(format!(
+                "ALTER TABLE {} MODIFY COLUMN {} {};",
+                table_name,
+                name,
+                // a primary key cannot be declared twice
+                def.replace(" PRIMARY KEY", "")
+            )),
+            ColumnChange::Extra(_) => None,
+        }
+    }
+}
+
+/// Keywords which start column attributes following a type, such as `NOT NULL` and `DEFAULT 0`.
+const ATTRIBUTE_KEYWORDS: [&str; 14] = [
+    "not", "null", "default", "primary", "key", "unique", "auto_increment", "comment",
+    "collate", "character", "charset", "generated", "as", "references",
+];
+
+/// Extracts a normalized column type from a definition,
+/// e.g. `decimal(20,8)` from `DECIMAL(20, 8) NOT NULL` and `bigint unsigned` from `BIGINT UNSIGNED NOT NULL`.
+fn column_type(definition: &str) -> String {
+    let mut depth = 0;
+    let mut words = vec![String::new()];
+    for c in definition.trim().chars() {
+        match c {
+            '(' => depth += 1,
+            ')' => depth -= 1,
+            c if c.is_whitespace() && depth == 0 => {
+                words.push(String::new());
+                continue;
+            }
+            c if c.is_whitespace() => continue,
+            _ => (),
+        }
+        if let Some(w) = words.last_mut() {
+            w.push(c.to_ascii_lowercase());
+        }
+    }
+    words
+        .into_iter()
+        .filter(|w| !w.is_empty())
+        .take_while(|w| !ATTRIBUTE_KEYWORDS.contains(&w.as_str()))
+        .collect::<Vec<String>>()
+        .join(" ")
+}
+
+/// Removes display widths of integer types, which are omitted by MySQL 8.0.19 or later,
+/// e.g. `bigint unsigned` from `bigint(20) unsigned`.
+fn without_display_width(ty: &str) -> String {
+    let ints = ["tinyint", "smallint", "mediumint", "int", "bigint"];
+    match (ty.find('('), ty.find(')')) {
+        (Some(i), Some(j)) if i < j && ints.contains(&&ty[..i]) => {
+            format!("{}{}", &ty[..i], &ty[j + 1..])
+        }
+        _ => ty.to_owned(),
+    }
+}
+
+/// Compares columns of an existing table with definitions.
+pub fn diff_columns(existing: &[ColumnInfo], expected: &[TableDef]) -> Vec<ColumnChange> {
+    let mut changes = vec![];
+
+    for (i, def) in expected.iter().enumerate() {
+        let after = if i == 0 {
+            None
+        } else {
+            Some(expected[i - 1].name().to_owned())
+        };
+        match existing.iter().find(|c| c.name.eq_ignore_ascii_case(def.name())) {
+            None => changes.push(ColumnChange::Add(
+                def.name().to_owned(),
+                def.definition().to_owned(),
+                after,
+            )),
+            Some(c) => {
+                let expected_type = column_type(def.definition());
+                let expected_nullable = !def.definition().to_uppercase().contains("NOT NULL")
+                    && !def.definition().to_uppercase().contains("PRIMARY KEY");
+                if without_display_width(&expected_type)
+                    != without_display_width(&c.column_type.to_lowercase())
+                    || expected_nullable != c.nullable
+                {
+                    changes.push(ColumnChange::Modify(
+                        def.name().to_owned(),
+                        def.definition().to_owned(),
+                    ));
+                }
+            }
+        }
+    }
+
+    for c in existing {
+        if !expected.iter().any(|d| d.name().eq_ignore_ascii_case(&c.name)) {
+            changes.push(ColumnChange::Extra(c.name.clone()));
+        }
+    }
+
+    changes
+}
+
+/// A migrator which keeps tables of `MySQLWriterElement` up to date.
+///
+/// Versions of tables are stored in `pikmin_schema_version`.
+/// When `MySQLWriterElement::schema_version` is increased, `migration_stmts` are applied,
+/// and then the remaining column differences against `table_def` are resolved by `ALTER TABLE`.
+/// An existing table without a recorded version is regarded as version 1.
+#[derive(Debug)]
+pub struct Migrator {
+    connection: Pool,
+}
+
+impl Migrator {
+    /// Creates a migrator with a connection pool.
+    pub fn new(connection: Pool) -> Self {
+        Migrator { connection }
+    }
+
+    /// Returns the recorded schema version of a table, if any.
+    pub fn current_version(&self, table_name: &str) -> Result<Option<u32>> {
+        self.connection.prep_exec(CREATE_VERSION_TABLE_STMT, ())?;
+        let mut result = self.connection.prep_exec(
+            r"SELECT version FROM pikmin_schema_version WHERE table_name = :table_name;",
+            params! { "table_name" => table_name },
+        )?;
+        match result.next() {
+            Some(row) => Ok(row?.get::<u32, usize>(0)),
+            None => Ok(None),
+        }
+    }
+
+    /// Returns columns of an existing table. This is empty if the table does not exist.
+    pub fn columns(&self, table_name: &str) -> Result<Vec<ColumnInfo>> {
+        let result = self.connection.prep_exec(
+            r"SELECT COLUMN_NAME, COLUMN_TYPE, IS_NULLABLE FROM information_schema.COLUMNS WHERE TABLE_SCHEMA = DATABASE() AND TABLE_NAME = :table_name ORDER BY ORDINAL_POSITION;",
+            params! { "table_name" => table_name },
+        )?;
+        result
+            .map(|row| {
+                let row = row?;
+                Ok(ColumnInfo {
+                    name: row.get::<String, usize>(0).unwrap_or_default(),
+                    column_type: row.get::<String, usize>(1).unwrap_or_default(),
+                    nullable: row.get::<String, usize>(2).is_some_and(|s| s == "YES"),
+                })
+            })
+            .collect()
+    }
+
+    /// Returns statements which `migrate` would execute, without executing them.
+    ///
+    /// Column differences are computed against the current table, so they might differ
+    /// after `MySQLWriterElement::migration_stmts` are actually applied (e.g. renamed columns).
+    pub fn plan<T: MySQLWriterElement>(&self, table_name: &str) -> Result<Vec<String>> {
+        let existing = self.columns(table_name)?;
+        if existing.is_empty() {
+            return Ok(vec![T::create_table_stmt(table_name)]);
+        }
+        let mut stmts = self.versioned_stmts::<T>(table_name)?;
+        stmts.extend(self.column_stmts::<T>(table_name, &existing));
+        Ok(stmts)
+    }
+
+    /// Creates or alters a table to match `T`, and records its version.
+    /// Returns the executed statements.
+    pub fn migrate<T: MySQLWriterElement>(&self, table_name: &str) -> Result<Vec<String>> {
+        let mut executed = vec![];
+        if self.columns(table_name)?.is_empty() {
+            executed.push(T::create_table_stmt(table_name));
+        } else {
+            executed.extend(self.versioned_stmts::<T>(table_name)?);
+        }
+        self.execute(&executed)?;
+
+        let existing = self.columns(table_name)?;
+        let alters = self.column_stmts::<T>(table_name, &existing);
+        self.execute(&alters)?;
+        executed.extend(alters);
+
+        self.connection.prep_exec(
+            r"REPLACE INTO pikmin_schema_version (table_name, version, applied_at) VALUES (:table_name, :version, NOW(3));",
+            params! {
+                "table_name" => table_name,
+                "version" => T::schema_version(),
+            },
+        )?;
+        info!("{} is at version {}", table_name, T::schema_version());
+        Ok(executed)
+    }
+
+    fn versioned_stmts<T: MySQLWriterElement>(&self, table_name: &str) -> Result<Vec<String>> {
+        let version = T::schema_version();
+        // an existing table without a recorded version was created before versioning
+        let current = self.current_version(table_name)?.unwrap_or(1);
+        if current > version {
+            Err(Error::NewerSchemaVersion {
+                table: table_name.to_owned(),
+                found: current,
+                supported: version,
+            })
+        } else if current < version {
+            Ok(T::migration_stmts(table_name, current))
+        } else {
+            Ok(vec![])
+        }
+    }
+
+    fn column_stmts<T: MySQLWriterElement>(&self, table_name: &str, existing: &[ColumnInfo]) -> Vec<String> {
+        diff_columns(existing, &T::table_def())
+            .iter()
+            .filter_map(|c| {
+                let stmt = c.to_sql(table_name);
+                if stmt.is_none() {
+                    info!("{}: column {:?} is not in the definition", table_name, c);
+                }
+                stmt
+            })
+            .collect()
+    }
+
+    fn execute(&self, stmts: &[String]) -> Result<()> {
+        for stmt in stmts {
+            trace!("migrate: {}", stmt);
+            self.connection.prep_exec(stmt, ())?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn diff_columns_test() {
+        let existing = vec![
+            ColumnInfo {
+                name: "id".to_owned(),
+                column_type: "varchar(64)".to_owned(),
+                nullable: false,
+            },
+            ColumnInfo {
+                name: "traded_at".to_owned(),
+                column_type: "timestamp(3)".to_owned(),
+                nullable: false,
+            },
+            ColumnInfo {
+                name: "amount".to_owned(),
+                column_type: "float".to_owned(),
+                nullable: false,
+            },
+            ColumnInfo {
+                name: "legacy".to_owned(),
+                column_type: "int(11)".to_owned(),
+                nullable: true,
+            },
+        ];
+        let expected = vec![
+            TableDef::new("id", "VARCHAR(64) NOT NULL PRIMARY KEY"),
+            TableDef::new("traded_at", "TIMESTAMP(3) NOT NULL"),
+            TableDef::new("amount", "DECIMAL(20, 8) NOT NULL"),
+            TableDef::new("side", "VARCHAR(4) NOT NULL"),
+        ];
+
+        let changes = diff_columns(&existing, &expected);
+        assert_eq!(
+            changes,
+            vec![
+                ColumnChange::Modify("amount".to_owned(), "DECIMAL(20, 8) NOT NULL".to_owned()),
+                ColumnChange::Add(
+                    "side".to_owned(),
+                    "VARCHAR(4) NOT NULL".to_owned(),
+                    Some("amount".to_owned()),
+                ),
+                ColumnChange::Extra("legacy".to_owned()),
+            ],
+        );
+        assert_eq!(column_type("BIGINT UNSIGNED NOT NULL"), "bigint unsigned");
+        assert_eq!(column_type("DECIMAL(20, 8) DEFAULT 0"), "decimal(20,8)");
+        assert_eq!(without_display_width("bigint(20) unsigned"), "bigint unsigned");
+        let unsigned = [ColumnInfo {
+            name: "size".to_owned(),
+            column_type: "bigint(20) unsigned".to_owned(),
+            nullable: false,
+        }];
+        assert!(diff_columns(&unsigned, &[TableDef::new("size", "BIGINT UNSIGNED NOT NULL")]).is_empty());
+        assert_eq!(
+            diff_columns(&unsigned, &[TableDef::new("size", "BIGINT NOT NULL")]),
+            vec![ColumnChange::Modify("size".to_owned(), "BIGINT NOT NULL".to_owned())],
+        );
+
+        assert_eq!(
+            changes
+                .iter()
+                .filter_map(|c| c.to_sql("t"))
+                .collect::<Vec<String>>(),
+            vec![
+                "ALTER TABLE t MODIFY COLUMN amount DECIMAL(20, 8) NOT NULL;",
+                "ALTER TABLE t ADD COLUMN side VARCHAR(4) NOT NULL AFTER amount;",
+            ],
+        );
+    }
+}
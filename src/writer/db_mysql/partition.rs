@@ -0,0 +1,209 @@
+use chrono::{DateTime, Datelike, NaiveDate, TimeZone, Utc};
+use log::{info, trace, warn};
+use mysql::{params, Pool};
+
+use crate::error::Result;
+use crate::writer::db_mysql::{MySQLWriterElement, TableDef};
+
+/// Returns the first day of the month of a given date.
+fn month_of(date: NaiveDate) -> NaiveDate {
+    NaiveDate::from_ymd(date.year(), date.month(), 1)
+}
+
+/// Returns the first day of the next month.
+fn next_month(month: NaiveDate) -> NaiveDate {
+    if month.month() == 12 {
+        NaiveDate::from_ymd(month.year() + 1, 1, 1)
+    } else {
+        NaiveDate::from_ymd(month.year(), month.month() + 1, 1)
+    }
+}
+
+/// Returns a bound of a partition, as `UNIX_TIMESTAMP` of the first moment of a month in UTC.
+fn bound(month: NaiveDate) -> i64 {
+    Utc.from_utc_date(&month).and_hms(0, 0, 0).timestamp()
+}
+
+/// Returns a definition of a partition which contains rows in a given month.
+fn partition_def(month: NaiveDate) -> String {
+    format!(
+        "PARTITION p{} VALUES LESS THAN ({})",
+        month.format("%Y%m"),
+        bound(next_month(month))
+    )
+}
+
+/// Returns definitions of partitions for months in `[from, until)`.
+fn partition_defs(from: NaiveDate, until: NaiveDate) -> Vec<String> {
+    let mut defs = vec![];
+    let mut month = month_of(from);
+    while month < until {
+        defs.push(partition_def(month));
+        month = next_month(month);
+    }
+    defs
+}
+
+/// A range partitioning of a table by month, on a time column of `MySQLWriterElement`.
+///
+/// Partitions are created `months_ahead` months after the latest trade,
+/// and rows older than the first partition are stored in `p_min`.
+/// As MySQL requires every unique key to contain the partitioning column,
+/// the time column is appended to the primary key.
+#[derive(Debug, Clone)]
+pub struct MonthlyPartitioning {
+    months_ahead: u32,
+    /// The exclusive upper bound of existing partitions, if known.
+    until: Option<NaiveDate>,
+}
+
+impl MonthlyPartitioning {
+    /// Creates a partitioning which keeps `months_ahead` partitions ahead of the latest trade.
+    pub fn new(months_ahead: u32) -> Self {
+        MonthlyPartitioning {
+            months_ahead,
+            until: None,
+        }
+    }
+
+    /// Forgets existing partitions, so that they are read from the database again.
+    pub(crate) fn reset(&mut self) {
+        self.until = None;
+    }
+
+    /// Returns the exclusive upper bound of partitions required for a given time.
+    fn required_until(&self, latest: DateTime<Utc>) -> NaiveDate {
+        (0..=self.months_ahead).fold(month_of(latest.date().naive_utc()), |m, _| next_month(m))
+    }
+
+    /// Returns a string to create a partitioned table, with partitions from the month of `earliest`.
+    pub fn create_table_stmt<T: MySQLWriterElement>(
+        &self,
+        table_name: &str,
+        earliest: DateTime<Utc>,
+    ) -> String {
+        let time_column = T::time_column();
+        let defs = T::table_def();
+        let mut keys = defs
+            .iter()
+            .filter(|d| d.definition().to_uppercase().contains("PRIMARY KEY"))
+            .map(|d| d.name().to_owned())
+            .collect::<Vec<String>>();
+        if !keys.contains(&time_column) {
+            keys.push(time_column.clone());
+        }
+
+        let columns = defs
+            .iter()
+            .map(|TableDef(k, v)| format!("{} {}", k, v.replace(" PRIMARY KEY", "")))
+            .chain(Some(format!("PRIMARY KEY ({})", keys.join(", "))))
+            .chain(
+                T::index_names()
+                    .iter()
+                    .map(|name| format!("KEY `ind_{s}` (`{s}`)", s = name)),
+            )
+            .collect::<Vec<String>>();
+
+        let from = month_of(earliest.date().naive_utc());
+        let partitions = Some(format!("PARTITION p_min VALUES LESS THAN ({})", bound(from)))
+            .into_iter()
+            .chain(partition_defs(from, self.required_until(earliest)))
+            .collect::<Vec<String>>();
+
+        format!(
+            r"CREATE TABLE IF NOT EXISTS {} ( {} ) PARTITION BY RANGE (UNIX_TIMESTAMP({})) ( {} );",
+            table_name,
+            columns.join(", "),
+            time_column,
+            partitions.join(", ")
+        )
+    }
+
+    /// Returns a string to add partitions up to the month of `latest` and `months_ahead`, if needed.
+    fn add_partitions_stmt(&self, table_name: &str, latest: DateTime<Utc>) -> Option<String> {
+        let until = self.until?;
+        let defs = partition_defs(until, self.required_until(latest));
+        if defs.is_empty() {
+            None
+        } else {
+            Some(format!(
+                r"ALTER TABLE {} ADD PARTITION ( {} );",
+                table_name,
+                defs.join(", ")
+            ))
+        }
+    }
+
+    /// Reads the upper bound of existing partitions. This is `None` if the table is not partitioned.
+    fn read_until(connection: &Pool, table_name: &str) -> Result<Option<NaiveDate>> {
+        let result = connection.prep_exec(
+            r"SELECT PARTITION_DESCRIPTION FROM information_schema.PARTITIONS WHERE TABLE_SCHEMA = DATABASE() AND TABLE_NAME = :table_name AND PARTITION_NAME IS NOT NULL;",
+            params! { "table_name" => table_name },
+        )?;
+        let mut bounds = vec![];
+        for row in result {
+            if let Some(b) = row?
+                .get::<String, usize>(0)
+                .and_then(|s| s.trim().parse::<i64>().ok())
+            {
+                bounds.push(b);
+            }
+        }
+        Ok(bounds
+            .into_iter()
+            .max()
+            .map(|b| Utc.timestamp(b, 0).date().naive_utc()))
+    }
+
+    /// Adds partitions so that trades until `latest` can be stored.
+    /// Returns false if the table turns out not to be partitioned.
+    pub(crate) fn ensure(
+        &mut self,
+        connection: &Pool,
+        table_name: &str,
+        latest: DateTime<Utc>,
+    ) -> Result<bool> {
+        if self.until.is_none() {
+            self.until = Self::read_until(connection, table_name)?;
+            if self.until.is_none() {
+                warn!("{} is not partitioned, so partitions are not added", table_name);
+                return Ok(false);
+            }
+        }
+        if let Some(stmt) = self.add_partitions_stmt(table_name, latest) {
+            trace!("partition: {}", stmt);
+            connection.prep_exec(&stmt, ())?;
+            let until = self.required_until(latest);
+            info!("{} is partitioned until {}", table_name, until);
+            self.until = Some(until);
+        }
+        Ok(true)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::writer::Trade;
+
+    use super::*;
+
+    #[test]
+    fn monthly_partitioning_test() {
+        let mut p = MonthlyPartitioning::new(1);
+        let earliest = Utc.ymd(2019, 11, 15).and_hms(1, 0, 0);
+        assert_eq!(
+            p.create_table_stmt::<Trade>("t", earliest),
+            "CREATE TABLE IF NOT EXISTS t ( id VARCHAR(64) NOT NULL, traded_at TIMESTAMP(3) NOT NULL, amount FLOAT NOT NULL, price FLOAT NOT NULL, PRIMARY KEY (id, traded_at), KEY `ind_traded_at` (`traded_at`) ) PARTITION BY RANGE (UNIX_TIMESTAMP(traded_at)) ( PARTITION p_min VALUES LESS THAN (1572566400), PARTITION p201911 VALUES LESS THAN (1575158400), PARTITION p201912 VALUES LESS THAN (1577836800) );",
+        );
+
+        // unknown until read from the database
+        assert_eq!(p.add_partitions_stmt("t", earliest), None);
+
+        p.until = Some(NaiveDate::from_ymd(2020, 2, 1));
+        assert_eq!(p.add_partitions_stmt("t", Utc.ymd(2019, 12, 31).and_hms(0, 0, 0)), None);
+        assert_eq!(
+            p.add_partitions_stmt("t", Utc.ymd(2020, 2, 1).and_hms(0, 0, 0)),
+            Some("ALTER TABLE t ADD PARTITION ( PARTITION p202002 VALUES LESS THAN (1583020800), PARTITION p202003 VALUES LESS THAN (1585699200) );".to_owned()),
+        );
+    }
+}
@@ -0,0 +1,253 @@
+use log::{info, trace};
+use postgres::binary_copy::BinaryCopyInWriter;
+use postgres::types::{ToSql, Type};
+use postgres::{Client, NoTls};
+
+use crate::error::Result;
+use crate::writer::db_mysql::TableDef;
+use crate::writer::Trade;
+use crate::writer::Writer;
+
+/// An constraint for PostgresWriter.
+pub trait PostgresWriterElement {
+    /// Converts a contents into values, in the order of `table_def`.
+    fn to_row(&self) -> Vec<Box<dyn ToSql + Sync>>;
+    /// Returns table definitions for PostgreSQL.
+    fn table_def() -> Vec<TableDef>;
+    /// Returns types of columns in the order of `table_def`. These are used by binary `COPY`.
+    fn column_types() -> Vec<Type>;
+    /// Returns columns which identify a row in addition to `exchange` and `symbol`.
+    fn key_columns() -> Vec<String>;
+    /// Returns a column used as the time dimension of a TimescaleDB hypertable.
+    fn time_column() -> String;
+    /// Returns a string to create a table.
+    ///
+    /// Every table has `exchange` and `symbol` columns ahead of `table_def`,
+    /// and the primary key is made up of them and `key_columns`.
+    /// TimescaleDB requires unique constraints to include the time dimension,
+    /// so `time_column` is added to the key for a hypertable.
+    fn create_table_stmt(table_name: &str, hypertable: bool) -> String {
+        let columns = Self::table_def()
+            .iter()
+            .map(|d| format!("{} {}", d.name(), d.definition()))
+            .collect::<Vec<String>>();
+
+        format!(
+            r"CREATE TABLE IF NOT EXISTS {} ( exchange TEXT NOT NULL, symbol TEXT NOT NULL, {}, PRIMARY KEY ({}) );",
+            table_name,
+            columns.join(", "),
+            Self::conflict_columns(hypertable).join(", ")
+        )
+    }
+
+    /// Returns a conflict target of upserts, which is the same as the primary key.
+    fn conflict_columns(hypertable: bool) -> Vec<String> {
+        let mut keys = vec!["exchange".to_owned(), "symbol".to_owned()];
+        keys.extend(Self::key_columns());
+        let time = Self::time_column();
+        if hypertable && !keys.contains(&time) {
+            keys.push(time);
+        }
+        keys
+    }
+}
+
+/// Returns a quoted name of the temporary table for a table name, which may be schema-qualified or quoted.
+/// Temporary tables belong to their own schema, so only the last identifier is used.
+fn staging_table_name(table_name: &str) -> String {
+    let mut last = String::new();
+    let mut quoted = false;
+    let mut in_quotes = false;
+    let mut chars = table_name.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '"' if in_quotes && chars.peek() == Some(&'"') => {
+                chars.next();
+                last.push('"');
+            }
+            '"' => {
+                in_quotes = !in_quotes;
+                quoted = true;
+            }
+            '.' if !in_quotes => {
+                last.clear();
+                quoted = false;
+            }
+            c => last.push(c),
+        }
+    }
+    // unquoted identifiers are folded to lower case by PostgreSQL
+    let name = if quoted { last } else { last.to_lowercase() };
+    format!("\"{}_staging\"", name.replace('"', "\"\""))
+}
+
+/// A writer implementation for PostgreSQL and TimescaleDB.
+///
+/// Trades are loaded into a temporary table by `COPY ... FROM STDIN BINARY`,
+/// then moved into the table with `ON CONFLICT ... DO NOTHING` in the same transaction,
+/// so writing the same trades twice is harmless.
+pub struct PostgresWriter {
+    table_name: String,
+    exchange: String,
+    symbol: String,
+    hypertable: bool,
+    table_created: bool,
+    client: Client,
+}
+
+impl std::fmt::Debug for PostgresWriter {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.debug_struct("PostgresWriter")
+            .field("table_name", &self.table_name)
+            .field("exchange", &self.exchange)
+            .field("symbol", &self.symbol)
+            .field("hypertable", &self.hypertable)
+            .finish()
+    }
+}
+
+impl PostgresWriter {
+    /// Creates a writer for PostgreSQL with a given URL and a table name.
+    /// `exchange` and `symbol` are stored with every trade.
+    pub fn new(table_name: &str, database_url: &str, exchange: &str, symbol: &str) -> Result<Self> {
+        trace!("connect to {}", database_url);
+        let client = Client::connect(database_url, NoTls)?;
+
+        Ok(PostgresWriter {
+            table_name: table_name.to_owned(),
+            exchange: exchange.to_owned(),
+            symbol: symbol.to_owned(),
+            hypertable: false,
+            table_created: false,
+            client,
+        })
+    }
+
+    /// Converts the table into a TimescaleDB hypertable on creation.
+    pub fn with_hypertable(mut self) -> Self {
+        self.hypertable = true;
+        self
+    }
+
+    fn create_table<T: PostgresWriterElement>(&mut self) -> Result<()> {
+        if self.table_created {
+            return Ok(());
+        }
+        let create_stmt = T::create_table_stmt(&self.table_name, self.hypertable);
+        self.client.batch_execute(&create_stmt)?;
+        if self.hypertable {
+            let hypertable_stmt = format!(
+                "SELECT create_hypertable('{}', '{}', if_not_exists => TRUE);",
+                self.table_name,
+                T::time_column()
+            );
+            self.client.batch_execute(&hypertable_stmt)?;
+        }
+        self.table_created = true;
+        Ok(())
+    }
+
+    fn copy_upsert<T: PostgresWriterElement>(&mut self, v: &[T]) -> Result<u64> {
+        let mut col_names = vec!["exchange".to_owned(), "symbol".to_owned()];
+        col_names.extend(T::table_def().iter().map(|d| d.name().to_owned()));
+        let cols = col_names.join(", ");
+        let mut types = vec![Type::TEXT, Type::TEXT];
+        types.extend(T::column_types());
+        let staging = staging_table_name(&self.table_name);
+
+        let mut tx = self.client.transaction()?;
+        tx.batch_execute(&format!(
+            "CREATE TEMPORARY TABLE IF NOT EXISTS {} (LIKE {} INCLUDING DEFAULTS) ON COMMIT DELETE ROWS;",
+            staging, self.table_name
+        ))?;
+
+        let sink = tx.copy_in(&format!("COPY {} ({}) FROM STDIN BINARY", staging, cols))?;
+        let mut writer = BinaryCopyInWriter::new(sink, &types);
+        for e in v {
+            let row = e.to_row();
+            let mut values: Vec<&(dyn ToSql + Sync)> = vec![&self.exchange, &self.symbol];
+            values.extend(row.iter().map(|b| b.as_ref()));
+            writer.write(&values)?;
+        }
+        writer.finish()?;
+
+        let inserted = tx.execute(
+            format!(
+                "INSERT INTO {} ({cols}) SELECT {cols} FROM {} ON CONFLICT ({}) DO NOTHING;",
+                self.table_name,
+                staging,
+                T::conflict_columns(self.hypertable).join(", "),
+                cols = cols
+            )
+                .as_str(),
+            &[],
+        )?;
+        tx.commit()?;
+        info!("skipped {} duplicated data", v.len() as u64 - inserted);
+        Ok(inserted)
+    }
+}
+
+impl PostgresWriterElement for Trade {
+    fn to_row(&self) -> Vec<Box<dyn ToSql + Sync>> {
+        vec![
+            Box::new(self.id.clone()),
+            Box::new(self.traded_at),
+            Box::new(self.quantity),
+            Box::new(self.price),
+        ]
+    }
+
+    fn table_def() -> Vec<TableDef> {
+        vec![
+            TableDef::new("id", "TEXT NOT NULL"),
+            TableDef::new("traded_at", "TIMESTAMPTZ(3) NOT NULL"),
+            TableDef::new("amount", "REAL NOT NULL"),
+            TableDef::new("price", "REAL NOT NULL"),
+        ]
+    }
+
+    fn column_types() -> Vec<Type> {
+        vec![Type::TEXT, Type::TIMESTAMPTZ, Type::FLOAT4, Type::FLOAT4]
+    }
+
+    fn key_columns() -> Vec<String> {
+        vec!["id".to_owned()]
+    }
+
+    fn time_column() -> String {
+        "traded_at".to_owned()
+    }
+}
+
+impl Writer for PostgresWriter {
+    fn write(&mut self, trades: &[Trade]) -> Result<u64> {
+        self.create_table::<Trade>()?;
+        self.copy_upsert(trades)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn postgres_create_table_test() {
+        assert_eq!(
+            "CREATE TABLE IF NOT EXISTS test ( exchange TEXT NOT NULL, symbol TEXT NOT NULL, id TEXT NOT NULL, traded_at TIMESTAMPTZ(3) NOT NULL, amount REAL NOT NULL, price REAL NOT NULL, PRIMARY KEY (exchange, symbol, id) );",
+            Trade::create_table_stmt("test", false),
+        );
+        assert_eq!(
+            vec!["exchange", "symbol", "id", "traded_at"],
+            Trade::conflict_columns(true),
+        );
+    }
+
+    #[test]
+    fn postgres_staging_table_test() {
+        assert_eq!(staging_table_name("trades"), r#""trades_staging""#);
+        assert_eq!(staging_table_name("public.Trades"), r#""trades_staging""#);
+        assert_eq!(staging_table_name(r#""My Schema"."Bf.Trades""#), r#""Bf.Trades_staging""#);
+        assert_eq!(staging_table_name(r#"public."a""b""#), r#""a""b_staging""#);
+    }
+}
@@ -0,0 +1,290 @@
+use std::path::Path;
+use std::sync::{Arc, Mutex, MutexGuard};
+
+use log::{info, trace};
+use rusqlite::types::ToSql;
+use rusqlite::{Connection, NO_PARAMS};
+
+use crate::error::Result;
+use crate::recorder::sqlite::SQLiteRecorder;
+use crate::recorder::ProgressRecorder;
+use crate::writer::db_mysql::TableDef;
+use crate::writer::Trade;
+use crate::writer::TransactionalWriter;
+use crate::writer::Writer;
+
+/// An constraint for SQLiteWriter.
+pub trait SQLiteWriterElement {
+    /// Converts a contents into values, in the order of `table_def`.
+    fn to_row(&self) -> Vec<Box<dyn ToSql>>;
+    /// Returns table definitions for SQLite.
+    fn table_def() -> Vec<TableDef>;
+    /// Optionally returns index definitions.
+    fn index_names() -> Vec<String>;
+    /// Returns statements to create a table and its indexes.
+    fn create_table_stmt(table_name: &str) -> String {
+        let columns = Self::table_def()
+            .iter()
+            .map(|d| format!("{} {}", d.name(), d.definition()))
+            .collect::<Vec<String>>();
+
+        let index = Self::index_names()
+            .iter()
+            .map(|name| {
+                format!(
+                    "CREATE INDEX IF NOT EXISTS ind_{t}_{s} ON {t} ({s});",
+                    t = table_name,
+                    s = name
+                )
+            })
+            .collect::<Vec<String>>();
+
+        format!(
+            "CREATE TABLE IF NOT EXISTS {} ( {} ); {}",
+            table_name,
+            columns.join(", "),
+            index.join(" ")
+        )
+    }
+}
+
+/// A connection shared by SQLiteWriter and SQLiteRecorder.
+pub(crate) type SharedConnection = Arc<Mutex<Connection>>;
+
+pub(crate) fn lock(shared: &Mutex<Connection>) -> MutexGuard<'_, Connection> {
+    shared.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+/// Runs `f` in a transaction, which is rolled back if `f` fails.
+fn in_transaction<T>(shared: &Mutex<Connection>, f: impl FnOnce(&Connection) -> Result<T>) -> Result<T> {
+    let conn = lock(shared);
+    conn.execute_batch("BEGIN;")?;
+    match f(&conn) {
+        Ok(v) => {
+            conn.execute_batch("COMMIT;")?;
+            Ok(v)
+        }
+        Err(e) => {
+            let _ = conn.execute_batch("ROLLBACK;");
+            Err(e)
+        }
+    }
+}
+
+/// A writer implementation for SQLite.
+///
+/// The database runs in WAL mode and each batch is written in a single transaction
+/// with `INSERT OR IGNORE`, so duplicated trades are skipped.
+///
+/// A recorder created by `recorder` stores progress into the same database,
+/// and `with_recorder` makes a writer for `Downloader::run_transactional`, which commits
+/// a batch of trades together with the progress, so an interruption between writing and recording
+/// never makes them disagree.
+///
+/// # Example
+///
+/// ```no_run
+/// use pikmin::writer::db_sqlite::SQLiteWriter;
+///
+/// let writer = SQLiteWriter::open("trades", "/tmp/trades.db").unwrap();
+/// let recorder = writer.recorder("liquid");
+/// let mut writer = writer.with_recorder(recorder);
+/// // downloader.run_transactional(&mut writer)
+/// ```
+#[derive(Debug)]
+pub struct SQLiteWriter {
+    table_name: String,
+    table_created: bool,
+    shared: SharedConnection,
+}
+
+impl SQLiteWriter {
+    /// Creates a writer with a given database file and a table name.
+    /// The file is created if it does not exist.
+    pub fn open<P: AsRef<Path>>(table_name: &str, path: P) -> Result<Self> {
+        trace!("open {}", path.as_ref().display());
+        let conn = Connection::open(path)?;
+        let mode: String = conn.query_row("PRAGMA journal_mode = WAL;", NO_PARAMS, |r| r.get(0))?;
+        trace!("journal mode: {}", mode);
+
+        Ok(SQLiteWriter {
+            table_name: table_name.to_owned(),
+            table_created: false,
+            shared: Arc::new(Mutex::new(conn)),
+        })
+    }
+
+    /// Creates a progress recorder for a job, sharing the database connection with this writer.
+    pub fn recorder(&self, job: &str) -> SQLiteRecorder {
+        SQLiteRecorder::with_connection(self.shared.clone(), job)
+    }
+
+    /// Creates a writer which records progress with a given recorder in the same transaction.
+    /// The progress is stored in the database of this writer, so use a recorder created by `recorder`.
+    pub fn with_recorder(self, recorder: SQLiteRecorder) -> SQLiteTransactionalWriter {
+        SQLiteTransactionalWriter {
+            writer: self,
+            recorder,
+        }
+    }
+
+    fn create_table<T: SQLiteWriterElement>(&mut self) -> Result<()> {
+        if !self.table_created {
+            let create_stmt = T::create_table_stmt(&self.table_name);
+            lock(&self.shared).execute_batch(&create_stmt)?;
+            self.table_created = true;
+        }
+        Ok(())
+    }
+
+    /// Inserts rows with a connection in a transaction.
+    fn batch_insert<T: SQLiteWriterElement>(&self, conn: &Connection, v: &[T]) -> Result<u64> {
+        let col_names = T::table_def()
+            .iter()
+            .map(|d| d.name().to_owned())
+            .collect::<Vec<String>>();
+        let stmt = format!(
+            "INSERT OR IGNORE INTO {} ({}) VALUES ({});",
+            self.table_name,
+            col_names.join(","),
+            col_names.iter().map(|_| "?").collect::<Vec<&str>>().join(",")
+        );
+
+        let inserted: u64 = v
+            .iter()
+            .map(|e| {
+                let row = e.to_row();
+                conn.prepare_cached(&stmt)
+                    .and_then(|mut s| s.execute(row.iter().map(|b| b.as_ref())))
+                    .map(|n| n as u64)
+                    .map_err(Into::into)
+            })
+            .collect::<Result<Vec<u64>>>()?
+            .iter()
+            .sum();
+        info!("skipped {} duplicated data", v.len() as u64 - inserted);
+        Ok(inserted)
+    }
+}
+
+impl SQLiteWriterElement for Trade {
+    fn to_row(&self) -> Vec<Box<dyn ToSql>> {
+        vec![
+            Box::new(self.id.clone()),
+            Box::new(self.traded_at),
+            Box::new(f64::from(self.quantity)),
+            Box::new(f64::from(self.price)),
+        ]
+    }
+
+    fn table_def() -> Vec<TableDef> {
+        vec![
+            TableDef::new("id", "TEXT NOT NULL PRIMARY KEY"),
+            TableDef::new("traded_at", "TEXT NOT NULL"),
+            TableDef::new("amount", "REAL NOT NULL"),
+            TableDef::new("price", "REAL NOT NULL"),
+        ]
+    }
+
+    fn index_names() -> Vec<String> {
+        vec!["traded_at".to_owned()]
+    }
+}
+
+impl Writer for SQLiteWriter {
+    fn write(&mut self, trades: &[Trade]) -> Result<u64> {
+        self.create_table::<Trade>()?;
+        in_transaction(&self.shared, |conn| self.batch_insert(conn, trades))
+    }
+}
+
+/// A writer implementation for SQLite, which commits trades and progress in one transaction.
+/// Use this with `Downloader::run_transactional`. See `SQLiteWriter::with_recorder`.
+#[derive(Debug)]
+pub struct SQLiteTransactionalWriter {
+    writer: SQLiteWriter,
+    recorder: SQLiteRecorder,
+}
+
+impl ProgressRecorder for SQLiteTransactionalWriter {
+    fn read(&self) -> Result<String> {
+        self.recorder.read()
+    }
+
+    fn out(&mut self, json: &str) -> Result<()> {
+        self.recorder.out(json)
+    }
+}
+
+impl TransactionalWriter for SQLiteTransactionalWriter {
+    fn write_with_progress(&mut self, trades: &[Trade], json: &str) -> Result<u64> {
+        self.writer.create_table::<Trade>()?;
+        self.recorder.create_table()?;
+        let (writer, recorder) = (&self.writer, &self.recorder);
+        in_transaction(&writer.shared, |conn| {
+            let inserted = writer.batch_insert(conn, trades)?;
+            recorder.write_progress(conn, json)?;
+            Ok(inserted)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::offset::TimeZone;
+    use chrono::Utc;
+
+    use super::*;
+
+    #[test]
+    fn sqlite_commit_with_progress_test() {
+        let path = std::env::temp_dir().join(format!("pikmin-sqlite-{}.db", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let count = |w: &SQLiteWriter| -> i64 {
+            lock(&w.shared)
+                .query_row("SELECT COUNT(*) FROM trades", NO_PARAMS, |r| r.get(0))
+                .unwrap()
+        };
+        let trades = vec![
+            Trade {
+                id: "1".to_string(),
+                traded_at: Utc.ymd(2019, 1, 1).and_hms(1, 1, 1),
+                quantity: 0.5,
+                price: 100.0,
+            },
+            Trade {
+                id: "2".to_string(),
+                traded_at: Utc.ymd(2019, 1, 1).and_hms(1, 1, 2),
+                quantity: -0.5,
+                price: 101.0,
+            },
+        ];
+
+        {
+            let writer = SQLiteWriter::open("trades", &path).unwrap();
+            let recorder = writer.recorder("job");
+            let mut writer = writer.with_recorder(recorder);
+            assert_eq!(writer.read().unwrap(), "");
+
+            assert_eq!(writer.write_with_progress(&trades, r#"{"current":3}"#).unwrap(), 2);
+            // duplicated trades are ignored
+            assert_eq!(writer.write_with_progress(&trades[1..], r#"{"current":3}"#).unwrap(), 0);
+        }
+        {
+            // a plain writer commits by itself
+            let mut writer = SQLiteWriter::open("trades", &path).unwrap();
+            let more = vec![Trade {
+                id: "3".to_string(),
+                ..trades[0].clone()
+            }];
+            assert_eq!(writer.write(&more).unwrap(), 1);
+        }
+
+        let writer = SQLiteWriter::open("trades", &path).unwrap();
+        let recorder = SQLiteRecorder::open(&path, "job").unwrap();
+        assert_eq!(count(&writer), 3);
+        assert_eq!(recorder.read().unwrap(), r#"{"current":3}"#);
+
+        std::fs::remove_file(&path).unwrap();
+    }
+}
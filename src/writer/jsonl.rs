@@ -0,0 +1,137 @@
+use std::io::{BufRead, Write};
+
+use log::{info, trace};
+
+use crate::error::Result;
+use crate::writer::Trade;
+use crate::writer::Writer;
+
+/// A writer implementation for JSON Lines (NDJSON).
+/// Each trade is serialized as one line into any `io::Write`, such as a file, stdout or a pipe.
+#[derive(Debug)]
+pub struct JsonLinesWriter<W: Write> {
+    inner: W,
+}
+
+impl<W: Write> JsonLinesWriter<W> {
+    /// Creates a writer with a given output.
+    pub fn new(inner: W) -> Self {
+        Self { inner }
+    }
+
+    /// Unwraps this writer, returning the underlying output.
+    pub fn into_inner(self) -> W {
+        self.inner
+    }
+}
+
+impl<W: Write> Writer for JsonLinesWriter<W> {
+    fn write(&mut self, trades: &[Trade]) -> Result<u64> {
+        for v in trades {
+            serde_json::to_writer(&mut self.inner, v)?;
+            self.inner.write_all(b"\n")?;
+        }
+        self.inner.flush()?;
+        Ok(trades.len() as u64)
+    }
+}
+
+/// A reader for archives written by `JsonLinesWriter`.
+/// Blank lines are skipped.
+#[derive(Debug)]
+pub struct JsonLinesReader<R: BufRead> {
+    inner: R,
+    line: String,
+}
+
+impl<R: BufRead> JsonLinesReader<R> {
+    /// Creates a reader with a given input.
+    pub fn new(inner: R) -> Self {
+        Self {
+            inner,
+            line: String::new(),
+        }
+    }
+
+    /// Reads all remaining trades and writes them into a given writer, `batch_size` trades at once.
+    /// Returns the sum of numbers reported by the writer.
+    pub fn replay(&mut self, writer: &mut impl Writer, batch_size: usize) -> Result<u64> {
+        let mut written = 0;
+        let mut batch = Vec::with_capacity(batch_size);
+        for trade in self {
+            batch.push(trade?);
+            if batch.len() >= batch_size {
+                written += writer.write(&batch)?;
+                batch.clear();
+            }
+        }
+        if !batch.is_empty() {
+            written += writer.write(&batch)?;
+        }
+        info!("replayed {} data", written);
+        Ok(written)
+    }
+}
+
+impl<R: BufRead> Iterator for JsonLinesReader<R> {
+    type Item = Result<Trade>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            self.line.clear();
+            match self.inner.read_line(&mut self.line) {
+                Ok(0) => return None,
+                Ok(_) if self.line.trim().is_empty() => continue,
+                Ok(_) => {
+                    trace!("read: {}", self.line.trim_end());
+                    return Some(serde_json::from_str(&self.line).map_err(Into::into));
+                }
+                Err(e) => return Some(Err(e.into())),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::offset::TimeZone;
+    use chrono::Utc;
+
+    use crate::testing::MockWriter;
+
+    use super::*;
+
+    #[test]
+    fn jsonl_round_trip_test() {
+        let trades = vec![
+            Trade {
+                id: "1".to_string(),
+                traded_at: Utc.ymd(2019, 1, 1).and_hms_milli(1, 1, 1, 123),
+                quantity: 0.5,
+                price: 3800.5,
+            },
+            Trade {
+                id: "2".to_string(),
+                traded_at: Utc.ymd(2019, 1, 1).and_hms(1, 1, 2),
+                quantity: -1.25,
+                price: 3801.0,
+            },
+            Trade {
+                id: "3".to_string(),
+                traded_at: Utc.ymd(2019, 1, 1).and_hms(1, 1, 3),
+                quantity: 2.0,
+                price: 3799.0,
+            },
+        ];
+
+        let mut writer = JsonLinesWriter::new(vec![]);
+        assert_eq!(writer.write(&trades).unwrap(), 3);
+        let archive = writer.into_inner();
+        assert_eq!(String::from_utf8_lossy(&archive).lines().count(), 3);
+
+        let mut replayed = MockWriter::new();
+        let mut reader = JsonLinesReader::new(archive.as_slice());
+        assert_eq!(reader.replay(&mut replayed, 2).unwrap(), 3);
+        assert_eq!(replayed.store, trades);
+    }
+}
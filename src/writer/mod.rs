@@ -1,17 +1,31 @@
 use chrono::DateTime;
+use chrono::SecondsFormat;
 use chrono::Utc;
 use mysql::{params, Params};
 use serde_derive::{Deserialize, Serialize};
 
+use crate::error::Error;
 use crate::error::Result;
+use crate::recorder::ProgressRecorder;
 use crate::writer::db_mysql::MySQLWriterElement;
 use crate::writer::db_mysql::TableDef;
 use crate::writer::stdout::StdOutWriterElement;
 
+/// A writer implementation for ClickHouse.
+pub mod db_clickhouse;
 /// A writer implementation for MySQL.
 pub mod db_mysql;
-#[cfg(test)]
-pub mod mock;
+/// A writer implementation for PostgreSQL and TimescaleDB.
+#[cfg(feature = "postgres")]
+pub mod db_postgres;
+/// A writer implementation for SQLite.
+#[cfg(feature = "sqlite")]
+pub mod db_sqlite;
+/// A writer and a reader for JSON Lines.
+pub mod jsonl;
+/// A writer implementation for Parquet files.
+#[cfg(feature = "parquet")]
+pub mod parquet;
 /// A writer implementation for stdout.
 pub mod stdout;
 
@@ -70,6 +84,22 @@ impl StdOutWriterElement for Trade {
     fn to_string(&self) -> String {
         format!("{:?}", self)
     }
+
+    fn to_fields(&self) -> Vec<(&'static str, String)> {
+        vec![
+            ("id", self.id.clone()),
+            (
+                "traded_at",
+                self.traded_at.to_rfc3339_opts(SecondsFormat::Millis, true),
+            ),
+            ("quantity", self.quantity.to_string()),
+            ("price", self.price.to_string()),
+        ]
+    }
+
+    fn to_json(&self) -> Result<String> {
+        serde_json::to_string(self).map_err(Error::from)
+    }
 }
 
 /// An abstraction of recorders. See StdOutWriter for an usage example.
@@ -78,6 +108,14 @@ pub trait Writer {
     fn write(&mut self, trades: &[Trade]) -> Result<u64>;
 }
 
+/// An abstraction of writers which also record progress.
+/// Trades and progress are stored atomically, so they never disagree after a crash.
+/// See `Downloader::run_transactional`.
+pub trait TransactionalWriter: ProgressRecorder {
+    /// Records `trades` and progress as `json` in a single transaction.
+    fn write_with_progress(&mut self, trades: &[Trade], json: &str) -> Result<u64>;
+}
+
 
 #[cfg(test)]
 mod tests {
@@ -0,0 +1,328 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::fs::File;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use arrow::array::{ArrayRef, Float32Array, StringArray};
+use arrow::array::{TimestampMillisecondArray, TimestampNanosecondArray};
+use arrow::datatypes::{DataType, Field, Schema, TimeUnit};
+use arrow::error::ArrowError;
+use arrow::record_batch::RecordBatch;
+use chrono::NaiveDate;
+use log::{info, trace, warn};
+use parquet::arrow::ArrowWriter;
+
+use crate::error::Result;
+use crate::writer::Trade;
+use crate::writer::Writer;
+
+/// A resolution of the `traded_at` column.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TimestampUnit {
+    /// Milliseconds since the epoch.
+    Millisecond,
+    /// Nanoseconds since the epoch.
+    Nanosecond,
+}
+
+impl TimestampUnit {
+    fn arrow_unit(self) -> TimeUnit {
+        match self {
+            TimestampUnit::Millisecond => TimeUnit::Millisecond,
+            TimestampUnit::Nanosecond => TimeUnit::Nanosecond,
+        }
+    }
+}
+
+/// A writer implementation for Parquet files.
+///
+/// Trades are buffered and flushed as a row group when the buffer reaches
+/// `row_group_size` trades or when `flush_interval` has elapsed since the last flush.
+/// Files are partitioned by the date of `traded_at`, e.g. `<dir>/date=2019-01-01/part-0.parquet`.
+///
+/// Parquet files are only readable after their footer is written, so call `close`
+/// (or drop the writer) when downloading is finished.
+#[derive(Debug)]
+pub struct ParquetWriter {
+    dir: PathBuf,
+    unit: TimestampUnit,
+    row_group_size: usize,
+    flush_interval: Duration,
+    schema: Arc<Schema>,
+    buffer: Vec<Trade>,
+    last_flush: Instant,
+    files: BTreeMap<NaiveDate, ArrowWriter<File>>,
+}
+
+impl ParquetWriter {
+    /// Creates a writer which stores partitioned files under a given directory.
+    pub fn new(dir: PathBuf) -> Self {
+        let unit = TimestampUnit::Millisecond;
+        Self {
+            dir,
+            unit,
+            row_group_size: 100_000,
+            flush_interval: Duration::from_secs(60 * 10),
+            schema: Arc::new(Self::schema(unit)),
+            buffer: vec![],
+            last_flush: Instant::now(),
+            files: BTreeMap::new(),
+        }
+    }
+
+    /// Sets the resolution of the `traded_at` column. The default is milliseconds.
+    pub fn with_timestamp_unit(mut self, unit: TimestampUnit) -> Self {
+        self.unit = unit;
+        self.schema = Arc::new(Self::schema(unit));
+        self
+    }
+
+    /// Sets the number of trades which triggers flushing a row group.
+    pub fn with_row_group_size(mut self, row_group_size: usize) -> Self {
+        self.row_group_size = row_group_size;
+        self
+    }
+
+    /// Sets the maximum time to keep trades in the buffer.
+    pub fn with_flush_interval(mut self, flush_interval: Duration) -> Self {
+        self.flush_interval = flush_interval;
+        self
+    }
+
+    /// Returns the Arrow schema derived from `Trade`.
+    pub fn schema(unit: TimestampUnit) -> Schema {
+        Schema::new(vec![
+            Field::new("id", DataType::Utf8, false),
+            Field::new(
+                "traded_at",
+                DataType::Timestamp(unit.arrow_unit(), Some("UTC".into())),
+                false,
+            ),
+            Field::new("price", DataType::Float32, false),
+            Field::new("quantity", DataType::Float32, false),
+            Field::new("side", DataType::Utf8, false),
+        ])
+    }
+
+    fn to_record_batch(&self, trades: &[&Trade]) -> Result<RecordBatch> {
+        let ids = StringArray::from_iter_values(trades.iter().map(|t| t.id.as_str()));
+        let traded_at: ArrayRef = match self.unit {
+            TimestampUnit::Millisecond => Arc::new(
+                TimestampMillisecondArray::from_iter_values(
+                    trades.iter().map(|t| t.traded_at.timestamp_millis()),
+                )
+                    .with_timezone("UTC"),
+            ),
+            TimestampUnit::Nanosecond => {
+                let nanos = trades
+                    .iter()
+                    .map(|t| {
+                        t.traded_at.timestamp_nanos_opt().ok_or_else(|| {
+                            ArrowError::InvalidArgumentError(format!(
+                                "{} of trade {} is out of range of nanosecond timestamps",
+                                t.traded_at, t.id
+                            ))
+                        })
+                    })
+                    .collect::<std::result::Result<Vec<i64>, _>>()?;
+                Arc::new(TimestampNanosecondArray::from_iter_values(nanos).with_timezone("UTC"))
+            }
+        };
+        let prices = Float32Array::from_iter_values(trades.iter().map(|t| t.price));
+        let quantities = Float32Array::from_iter_values(trades.iter().map(|t| t.quantity.abs()));
+        let sides = StringArray::from_iter_values(
+            trades
+                .iter()
+                .map(|t| if t.quantity < 0.0 { "sell" } else { "buy" }),
+        );
+
+        let columns: Vec<ArrayRef> = vec![
+            Arc::new(ids),
+            traded_at,
+            Arc::new(prices),
+            Arc::new(quantities),
+            Arc::new(sides),
+        ];
+        RecordBatch::try_new(self.schema.clone(), columns).map_err(Into::into)
+    }
+
+    fn open_partition(&self, date: NaiveDate) -> Result<ArrowWriter<File>> {
+        let dir = self.dir.join(format!("date={}", date.format("%Y-%m-%d")));
+        fs::create_dir_all(&dir)?;
+
+        // never overwrite files written by a previous run
+        let path = (0..)
+            .map(|n| dir.join(format!("part-{}.parquet", n)))
+            .find(|p| !p.exists())
+            .unwrap_or_default();
+        trace!("open {}", path.display());
+
+        let file = File::create(&path)?;
+        ArrowWriter::try_new(file, self.schema.clone(), None).map_err(Into::into)
+    }
+
+    /// Writes buffered trades as row groups of at most `row_group_size` trades.
+    /// Files of dates which do not appear in this flush are closed.
+    pub fn flush(&mut self) -> Result<()> {
+        let buffer = std::mem::take(&mut self.buffer);
+        self.last_flush = Instant::now();
+        if buffer.is_empty() {
+            return Ok(());
+        }
+
+        let mut partitions: BTreeMap<NaiveDate, Vec<&Trade>> = BTreeMap::new();
+        for t in buffer.iter() {
+            partitions.entry(t.traded_at.date_naive()).or_default().push(t);
+        }
+
+        let stale: Vec<NaiveDate> = self
+            .files
+            .keys()
+            .filter(|d| !partitions.contains_key(d))
+            .cloned()
+            .collect();
+        for date in stale {
+            if let Some(w) = self.files.remove(&date) {
+                w.close()?;
+            }
+        }
+
+        for (date, trades) in partitions {
+            let batches = trades
+                .chunks(self.row_group_size.max(1))
+                .map(|chunk| self.to_record_batch(chunk))
+                .collect::<Result<Vec<_>>>()?;
+            if !self.files.contains_key(&date) {
+                let w = self.open_partition(date)?;
+                self.files.insert(date, w);
+            }
+            if let Some(w) = self.files.get_mut(&date) {
+                // each flush closes a row group
+                for batch in batches {
+                    w.write(&batch)?;
+                    w.flush()?;
+                }
+            }
+        }
+        info!("flushed {} trades", buffer.len());
+        Ok(())
+    }
+
+    /// Flushes remaining trades and finalizes all files.
+    pub fn close(mut self) -> Result<()> {
+        self.close_all()
+    }
+
+    fn close_all(&mut self) -> Result<()> {
+        self.flush()?;
+        let files = std::mem::take(&mut self.files);
+        for (_, w) in files {
+            w.close()?;
+        }
+        Ok(())
+    }
+}
+
+impl Writer for ParquetWriter {
+    fn write(&mut self, trades: &[Trade]) -> Result<u64> {
+        self.buffer.extend_from_slice(trades);
+        if self.buffer.len() >= self.row_group_size
+            || self.last_flush.elapsed() >= self.flush_interval
+        {
+            self.flush()?;
+        }
+        Ok(trades.len() as u64)
+    }
+}
+
+impl Drop for ParquetWriter {
+    fn drop(&mut self) {
+        if let Err(e) = self.close_all() {
+            warn!("cannot close parquet files: {}", e);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::offset::TimeZone;
+    use chrono::Utc;
+    use parquet::arrow::arrow_reader::ParquetRecordBatchReaderBuilder;
+
+    use super::*;
+
+    #[test]
+    fn parquet_partition_test() {
+        let dir = std::env::temp_dir().join(format!("pikmin-parquet-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+
+        let trades = vec![
+            Trade {
+                id: "1".to_string(),
+                traded_at: Utc.ymd(2019, 1, 1).and_hms(23, 59, 59),
+                quantity: 0.5,
+                price: 100.0,
+            },
+            Trade {
+                id: "2".to_string(),
+                traded_at: Utc.ymd(2019, 1, 2).and_hms(0, 0, 0),
+                quantity: -0.25,
+                price: 101.0,
+            },
+            Trade {
+                id: "3".to_string(),
+                traded_at: Utc.ymd(2019, 1, 2).and_hms(0, 0, 1),
+                quantity: 1.0,
+                price: 102.0,
+            },
+        ];
+
+        let mut writer = ParquetWriter::new(dir.clone()).with_row_group_size(2);
+        assert_eq!(writer.write(&trades).unwrap(), 3);
+        writer.close().unwrap();
+
+        let rows = |date: &str| {
+            let file = File::open(dir.join(format!("date={}", date)).join("part-0.parquet")).unwrap();
+            ParquetRecordBatchReaderBuilder::try_new(file)
+                .unwrap()
+                .build()
+                .unwrap()
+                .map(|b| b.unwrap().num_rows())
+                .sum::<usize>()
+        };
+        assert_eq!(rows("2019-01-01"), 1);
+        assert_eq!(rows("2019-01-02"), 2);
+        fs::remove_dir_all(&dir).unwrap();
+
+        // a write larger than the row group size is split into row groups
+        let many: Vec<Trade> = (0..5)
+            .map(|n| Trade {
+                id: n.to_string(),
+                traded_at: Utc.ymd(2019, 1, 1).and_hms(0, 0, n),
+                quantity: 1.0,
+                price: 100.0,
+            })
+            .collect();
+        let mut writer = ParquetWriter::new(dir.clone()).with_row_group_size(2);
+        writer.write(&many).unwrap();
+        writer.close().unwrap();
+        let file = File::open(dir.join("date=2019-01-01").join("part-0.parquet")).unwrap();
+        let builder = ParquetRecordBatchReaderBuilder::try_new(file).unwrap();
+        let sizes: Vec<i64> = builder.metadata().row_groups().iter().map(|g| g.num_rows()).collect();
+        assert_eq!(sizes, vec![2, 2, 1]);
+
+        // nanoseconds cannot represent dates far from the epoch
+        let mut writer = ParquetWriter::new(dir.clone()).with_timestamp_unit(TimestampUnit::Nanosecond);
+        let far = Trade {
+            traded_at: Utc.ymd(2300, 1, 1).and_hms(0, 0, 0),
+            ..many[0].clone()
+        };
+        writer.write(&[far]).unwrap();
+        assert!(writer.flush().is_err());
+        drop(writer);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -1,25 +1,225 @@
+use std::io;
+use std::io::Write;
+
 use log::trace;
 
 use crate::error::Result;
 use crate::writer::Trade;
 use crate::writer::Writer;
 
+/// An output format for StdOutWriter.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub enum StdOutFormat {
+    /// A representation by `StdOutWriterElement::to_string`.
+    #[default]
+    Debug,
+    /// Comma-separated values. Fields are quoted when needed.
+    Csv,
+    /// Tab-separated values.
+    Tsv,
+    /// One JSON object per line.
+    JsonLines,
+    /// A user-provided template. Each `{field}` is replaced by the value of the field,
+    /// e.g. `"{traded_at} {price}"`.
+    Template(String),
+}
+
 /// An constraint for StdOutWriter.
 pub trait StdOutWriterElement {
     /// Converts a contents into a string.
     fn to_string(&self) -> String;
+    /// Returns pairs of a field name and its value, in the order of columns.
+    /// The default is a single `value` field of `to_string`.
+    fn to_fields(&self) -> Vec<(&'static str, String)> {
+        vec![("value", self.to_string())]
+    }
+    /// Converts a contents into a JSON string.
+    /// The default is an object of `to_fields` with string values.
+    fn to_json(&self) -> Result<String> {
+        let object: serde_json::Map<String, serde_json::Value> = self
+            .to_fields()
+            .into_iter()
+            .map(|(k, v)| (k.to_owned(), serde_json::Value::String(v)))
+            .collect();
+        serde_json::to_string(&object).map_err(Into::into)
+    }
+
+    /// Converts a contents into a line with a given format.
+    fn format(&self, format: &StdOutFormat) -> Result<String> {
+        let line = match format {
+            StdOutFormat::Debug => self.to_string(),
+            StdOutFormat::Csv => self
+                .to_fields()
+                .iter()
+                .map(|(_, v)| escape_csv(v))
+                .collect::<Vec<String>>()
+                .join(","),
+            StdOutFormat::Tsv => self
+                .to_fields()
+                .iter()
+                .map(|(_, v)| v.replace(['\t', '\n'], " "))
+                .collect::<Vec<String>>()
+                .join("\t"),
+            StdOutFormat::JsonLines => self.to_json()?,
+            StdOutFormat::Template(t) => render_template(t, &self.to_fields()),
+        };
+        Ok(line)
+    }
+}
+
+/// Replaces each `{field}` in a template in one pass, so placeholders in values are kept as they are.
+/// Unknown fields and unclosed braces are kept too.
+fn render_template(template: &str, fields: &[(&'static str, String)]) -> String {
+    let mut line = String::with_capacity(template.len());
+    let mut rest = template;
+    while let Some(open) = rest.find('{') {
+        line.push_str(&rest[..open]);
+        let after = &rest[open + 1..];
+        let value = after
+            .find('}')
+            .and_then(|close| fields.iter().find(|(k, _)| *k == &after[..close]).map(|(_, v)| (close, v)));
+        match value {
+            Some((close, v)) => {
+                line.push_str(v);
+                rest = &after[close + 1..];
+            }
+            None => {
+                line.push('{');
+                rest = after;
+            }
+        }
+    }
+    line.push_str(rest);
+    line
+}
+
+fn escape_csv(v: &str) -> String {
+    if v.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", v.replace('"', "\"\""))
+    } else {
+        v.to_owned()
+    }
 }
 
 /// A writer implementation for stdout.
+///
+/// # Example
+///
+/// ```
+/// use pikmin::StdOutWriter;
+/// use pikmin::writer::stdout::StdOutFormat;
+///
+/// // CSV with a header line, e.g. for `| sort` or spreadsheets
+/// let writer = StdOutWriter::new(StdOutFormat::Csv).with_header();
+/// ```
 #[derive(Default, Debug)]
-pub struct StdOutWriter {}
+pub struct StdOutWriter {
+    format: StdOutFormat,
+    header: bool,
+    header_written: bool,
+}
+
+impl StdOutWriter {
+    /// Creates a writer with a given format.
+    pub fn new(format: StdOutFormat) -> Self {
+        Self {
+            format,
+            ..Default::default()
+        }
+    }
+
+    /// Prints a header line before the first trade. Only CSV and TSV have a header.
+    pub fn with_header(mut self) -> Self {
+        self.header = true;
+        self
+    }
+
+    fn header_line(&self, first: &impl StdOutWriterElement) -> Option<String> {
+        let names = first.to_fields().iter().map(|(k, _)| *k).collect::<Vec<&str>>();
+        match self.format {
+            StdOutFormat::Csv => Some(names.join(",")),
+            StdOutFormat::Tsv => Some(names.join("\t")),
+            _ => None,
+        }
+    }
+}
 
 impl Writer for StdOutWriter {
     fn write(&mut self, trades: &[Trade]) -> Result<u64> {
+        let stdout = io::stdout();
+        let mut out = stdout.lock();
+
+        if let (true, false, Some(first)) = (self.header, self.header_written, trades.first()) {
+            if let Some(h) = self.header_line(first) {
+                writeln!(out, "{}", h)?;
+            }
+            self.header_written = true;
+        }
+
         for v in trades {
-            trace!("write: {:?}", v.to_string());
-            println!("{:?}", v.to_string());
+            let line = v.format(&self.format)?;
+            trace!("write: {}", line);
+            writeln!(out, "{}", line)?;
         }
+        out.flush()?;
         Ok(trades.len() as u64)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use chrono::offset::TimeZone;
+    use chrono::Utc;
+
+    use super::*;
+
+    #[test]
+    fn stdout_format_test() {
+        let trade = Trade {
+            id: "a,b".to_string(),
+            traded_at: Utc.ymd(2019, 1, 1).and_hms_milli(1, 1, 1, 500),
+            quantity: -0.5,
+            price: 3800.0,
+        };
+
+        assert_eq!(
+            trade.format(&StdOutFormat::Csv).unwrap(),
+            r#""a,b",2019-01-01T01:01:01.500Z,-0.5,3800"#,
+        );
+        assert_eq!(
+            trade.format(&StdOutFormat::Tsv).unwrap(),
+            "a,b\t2019-01-01T01:01:01.500Z\t-0.5\t3800",
+        );
+        assert_eq!(
+            trade
+                .format(&StdOutFormat::Template("{price} {quantity}".to_string()))
+                .unwrap(),
+            "3800 -0.5",
+        );
+        // values are not rendered again, even if they look like placeholders
+        let tricky = Trade {
+            id: "{price}".to_string(),
+            ..trade.clone()
+        };
+        assert_eq!(
+            tricky
+                .format(&StdOutFormat::Template("{id} {price} {unknown} {".to_string()))
+                .unwrap(),
+            "{price} 3800 {unknown} {",
+        );
+        assert_eq!(
+            trade.format(&StdOutFormat::JsonLines).unwrap(),
+            r#"{"id":"a,b","traded_at":"2019-01-01T01:01:01.500Z","quantity":-0.5,"price":3800.0}"#,
+        );
+
+        // implementors with only `to_string` get defaults
+        struct Line;
+        impl StdOutWriterElement for Line {
+            fn to_string(&self) -> String {
+                "a line".to_owned()
+            }
+        }
+        assert_eq!(Line.format(&StdOutFormat::Csv).unwrap(), "a line");
+        assert_eq!(Line.format(&StdOutFormat::JsonLines).unwrap(), r#"{"value":"a line"}"#);
+    }
+}